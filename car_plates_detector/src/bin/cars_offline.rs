@@ -1,10 +1,17 @@
-use std::{thread, time::Duration};
+use std::env;
 
 use car_plates_detector::plate_detection::dnn_ocr::DnnOcrReader;
-use car_plates_detector::plate_detection::video_reader::VideoReader;
+use car_plates_detector::plate_detection::frames_summary;
+use car_plates_detector::plate_detection::inference::DevicePolicy;
+use car_plates_detector::plate_detection::plate_association::PlateAssociator;
+use car_plates_detector::plate_detection::video_reader::{
+    FrameClock, VideoReader, VideoSource, SAMPLE_VIDEO,
+};
 use car_plates_detector::plate_detection::{
-    bounding_box_render::BoundingBoxRender, object_detector::ObjectDetector,
+    bounding_box_render::BoundingBoxRender,
+    object_detector::{DetectionConfig, ObjectDetector},
 };
+use crossbeam::channel::{bounded, Receiver};
 use rusted_pipe::graph::metrics::Metrics;
 use rusted_pipe::{
     buffers::synchronizers::timestamp::TimestampSynchronizer,
@@ -15,12 +22,18 @@ use rusted_pipe::{
     },
 };
 
-fn setup_test(metrics: Metrics) -> Graph {
+fn setup_test(source: VideoSource, metrics: Metrics) -> (Graph, Receiver<()>) {
     // Create the nodes
-    // Node that reads the data from the input file
+    // Node that reads the data from the input file and tells us when it is done.
+    let (end_of_stream_s, end_of_stream_r) = bounded(1);
     let mut video_input_node = SourceNode::create_common(
         "video_input".to_string(),
-        Box::new(VideoReader::default(false, 5)),
+        Box::new(
+            VideoReader::new(source, false, 5)
+                .expect("Cannot create the video reader")
+                .with_clock(FrameClock::Media)
+                .notify_end_of_stream(end_of_stream_s),
+        ),
     );
 
     let timestamp_synch = TimestampSynchronizer::default();
@@ -28,7 +41,10 @@ fn setup_test(metrics: Metrics) -> Graph {
     // Node that performs bounding box detection for cars
     let mut car_detector_node = Node::create_common(
         "car_detector".to_string(),
-        Box::new(ObjectDetector::car_detector(true)),
+        Box::new(
+            ObjectDetector::car_detector(DevicePolicy::Auto, DetectionConfig::cars())
+                .expect("Cannot create the car detector"),
+        ),
         true,
         3000,
         3000,
@@ -39,7 +55,10 @@ fn setup_test(metrics: Metrics) -> Graph {
     // Node that performs bounding box detection for cars
     let mut plate_detector_node = Node::create_common(
         "plate_detector".to_string(),
-        Box::new(ObjectDetector::plate_detector(true)),
+        Box::new(
+            ObjectDetector::plate_detector(DevicePolicy::Auto, DetectionConfig::plates())
+                .expect("Cannot create the plate detector"),
+        ),
         true,
         3000,
         3000,
@@ -58,6 +77,17 @@ fn setup_test(metrics: Metrics) -> Graph {
         true,
     );
 
+    // Node that matches the OCR reads with the cars they belong to.
+    let mut plate_association_node = Node::create_common(
+        "plate_association".to_string(),
+        Box::new(PlateAssociator::default()),
+        true,
+        3000,
+        3000,
+        Box::new(timestamp_synch.clone()),
+        true,
+    );
+
     // Node that collects the inferred information and overlays it on top of the original video.
     let bbox_render_node = Node::create_common(
         "bbox_render".to_string(),
@@ -141,9 +171,33 @@ fn setup_test(metrics: Metrics) -> Graph {
     )
     .unwrap();
 
-    // OCR -> BoundingBox
+    // Car Detector -> Plate Association
+    rusted_pipe::graph::build::link(
+        car_detector_node.write_channel.writer.c1(),
+        plate_association_node
+            .read_channel
+            .channels
+            .write()
+            .unwrap()
+            .c1(),
+    )
+    .unwrap();
+
+    // OCR -> Plate Association
     rusted_pipe::graph::build::link(
         ocr_detector_node.write_channel.writer.c1(),
+        plate_association_node
+            .read_channel
+            .channels
+            .write()
+            .unwrap()
+            .c2(),
+    )
+    .unwrap();
+
+    // Plate Association -> BoundingBox
+    rusted_pipe::graph::build::link(
+        plate_association_node.write_channel.writer.c1(),
         bbox_render_node.read_channel.channels.write().unwrap().c2(),
     )
     .unwrap();
@@ -153,21 +207,34 @@ fn setup_test(metrics: Metrics) -> Graph {
 
     // We need to start each node independently
     graph.start_node(ocr_detector_node);
+    graph.start_node(plate_association_node);
     graph.start_node(plate_detector_node);
     graph.start_node(bbox_render_node);
     graph.start_node(car_detector_node);
     graph.start_source_node(video_input_node);
 
-    graph
+    (graph, end_of_stream_r)
 }
 
 fn main() {
     let metrics = Metrics::builder().with_prometheus(&default_prometheus_address());
-    let graph = setup_test(metrics);
+    // Any file, URI, V4L2 device index or GStreamer pipeline, defaults to the sample video.
+    let source = env::args().nth(1).unwrap_or(SAMPLE_VIDEO.to_string());
+    let (graph, end_of_stream) = setup_test(VideoSource::parse(&source), metrics);
 
     println!("Starting, waiting for video to end");
-    thread::sleep(Duration::from_millis(15000));
+    end_of_stream
+        .recv()
+        .expect("Video reader stopped without reaching the end of the video");
+
+    // Wait for all the queued frames to go through the detectors, the OCR and the renderer.
+    // The renderer releases its video file when the graph drops it.
+    println!("Video ended, draining the pipeline");
     graph.stop(true, None);
 
+    println!("Frames processed:");
+    for (node, frames) in frames_summary() {
+        println!("  {node}: {frames}");
+    }
     println!("Done");
 }
@@ -0,0 +1,35 @@
+use std::{env, process, thread, time::Duration};
+
+use car_plates_detector::pipeline::{builder::build_graph, config::PipelineConfig};
+use car_plates_detector::plate_detection::frames_summary;
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or("pipelines/cars_offline.yaml".to_string());
+
+    let config = PipelineConfig::from_file(&path).unwrap_or_else(|e| {
+        eprintln!("Invalid pipeline {path}: {e}");
+        process::exit(1);
+    });
+    let pipeline = build_graph(&config).unwrap_or_else(|e| {
+        eprintln!("Cannot build pipeline {path}: {e}");
+        process::exit(1);
+    });
+
+    if let Some(run_for_ms) = config.run_for_ms {
+        println!("Starting {path}, running for {run_for_ms}ms");
+        thread::sleep(Duration::from_millis(run_for_ms));
+    } else {
+        println!("Starting {path}, waiting for the sources to end");
+        pipeline.wait_end_of_stream();
+        println!("Sources ended, draining the pipeline");
+    }
+    pipeline.graph.stop(true, None);
+
+    println!("Frames processed:");
+    for (node, frames) in frames_summary() {
+        println!("  {node}: {frames}");
+    }
+    println!("Done");
+}
@@ -1,9 +1,13 @@
-use std::{thread, time::Duration};
+use std::{env, thread, time::Duration};
 
 use car_plates_detector::plate_detection::dnn_ocr::DnnOcrReader;
-use car_plates_detector::plate_detection::video_reader::VideoReader;
+use car_plates_detector::plate_detection::inference::DevicePolicy;
+use car_plates_detector::plate_detection::plate_association::PlateAssociator;
+use car_plates_detector::plate_detection::video_reader::{VideoReader, VideoSource, SAMPLE_VIDEO};
 use car_plates_detector::plate_detection::{
-    bounding_box_render::BoundingBoxRender, object_detector::ObjectDetector, rtp_sink::RtpSink,
+    bounding_box_render::BoundingBoxRender,
+    object_detector::{DetectionConfig, ObjectDetector},
+    rtp_sink::RtpSink,
 };
 use rusted_pipe::graph::metrics::{default_prometheus_address, Metrics};
 use rusted_pipe::{
@@ -14,13 +18,13 @@ use rusted_pipe::{
     },
 };
 
-fn setup_test() -> Graph {
+fn setup_test(source: VideoSource) -> Graph {
     // Create the nodes
 
     // Node that reads the data from the input file
     let mut video_input_node = SourceNode::create_common(
         "video_input".to_string(),
-        Box::new(VideoReader::default(true, 22)),
+        Box::new(VideoReader::new(source, true, 22).expect("Cannot create the video reader")),
     );
 
     let realtime_synch = RealTimeSynchronizer::new(1e8 as u128, true, false);
@@ -29,7 +33,10 @@ fn setup_test() -> Graph {
     // Node that performs bounding box detection for cars
     let mut car_detector_node = Node::create_common(
         "car_detector".to_string(),
-        Box::new(ObjectDetector::car_detector(true)),
+        Box::new(
+            ObjectDetector::car_detector(DevicePolicy::Auto, DetectionConfig::cars())
+                .expect("Cannot create the car detector"),
+        ),
         false,
         1,
         1,
@@ -40,7 +47,10 @@ fn setup_test() -> Graph {
     // Node that performs bounding box detection for cars
     let mut plate_detector_node = Node::create_common(
         "plate_detector".to_string(),
-        Box::new(ObjectDetector::plate_detector(true)),
+        Box::new(
+            ObjectDetector::plate_detector(DevicePolicy::Auto, DetectionConfig::plates())
+                .expect("Cannot create the plate detector"),
+        ),
         false,
         1,
         1,
@@ -59,6 +69,17 @@ fn setup_test() -> Graph {
         true,
     );
 
+    // Node that matches the OCR reads with the cars they belong to.
+    let mut plate_association_node = Node::create_common(
+        "plate_association".to_string(),
+        Box::new(PlateAssociator::default()),
+        false,
+        200,
+        1,
+        Box::new(realtime_synch.clone()),
+        true,
+    );
+
     // Node that collects the inferred information and overlays it on top of the original video.
     let mut bbox_render_node = Node::create_common(
         "bbox_render".to_string(),
@@ -153,9 +174,33 @@ fn setup_test() -> Graph {
     )
     .unwrap();
 
-    // OCR -> BoundingBox
+    // Car Detector -> Plate Association
+    rusted_pipe::graph::build::link(
+        car_detector_node.write_channel.writer.c1(),
+        plate_association_node
+            .read_channel
+            .channels
+            .write()
+            .unwrap()
+            .c1(),
+    )
+    .unwrap();
+
+    // OCR -> Plate Association
     rusted_pipe::graph::build::link(
         ocr_detector_node.write_channel.writer.c1(),
+        plate_association_node
+            .read_channel
+            .channels
+            .write()
+            .unwrap()
+            .c2(),
+    )
+    .unwrap();
+
+    // Plate Association -> BoundingBox
+    rusted_pipe::graph::build::link(
+        plate_association_node.write_channel.writer.c1(),
         bbox_render_node.read_channel.channels.write().unwrap().c2(),
     )
     .unwrap();
@@ -177,13 +222,16 @@ fn setup_test() -> Graph {
     graph.start_node(car_detector_node);
     graph.start_node(plate_detector_node);
     graph.start_node(ocr_detector_node);
+    graph.start_node(plate_association_node);
     graph.start_terminal_node(rtp_node);
 
     graph
 }
 
 fn main() {
-    let graph = setup_test();
+    // Any file, URI, V4L2 device index or GStreamer pipeline, defaults to the sample video.
+    let source = env::args().nth(1).unwrap_or(SAMPLE_VIDEO.to_string());
+    let graph = setup_test(VideoSource::parse(&source));
 
     println!("Starting, waiting for video to end");
     thread::sleep(Duration::from_millis(4000));
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use opencv::core::Point;
+use opencv::core::Size;
+use opencv::prelude::Mat;
+use rusted_pipe::buffers::synchronizers::real_time::RealTimeSynchronizer;
+use rusted_pipe::buffers::synchronizers::timestamp::TimestampSynchronizer;
+use rusted_pipe::buffers::synchronizers::PacketSynchronizer;
+use rusted_pipe::channels::typed_read_channel::{ReadChannel1, ReadChannel2, ReadChannel3};
+use rusted_pipe::channels::typed_write_channel::WriteChannel1;
+use rusted_pipe::graph::build::link;
+use rusted_pipe::graph::build::Graph;
+use rusted_pipe::graph::metrics::{default_prometheus_address, Metrics};
+use rusted_pipe::graph::processor::{Node, SourceNode, TerminalNode};
+use rusted_pipe::RustedPipeError;
+
+use super::config::{
+    NodeConfig, OcrEngineConfig, PipelineConfig, PlateFormatConfig, PlateNetworkConfig,
+    ProcessorConfig, RegionsConfig, SynchronizerConfig,
+};
+use super::ConfigError;
+use crate::plate_detection::bounding_box_render::BoundingBoxRender;
+use crate::plate_detection::cascade_detector::CascadePlateDetector;
+use crate::plate_detection::dnn_ocr::DnnOcrReader;
+use crate::plate_detection::image_reader::ImageSequenceReader;
+use crate::plate_detection::object_detector::{DetectionConfig, ObjectDetector};
+use crate::plate_detection::ocr_backend::{CrnnBackend, OcrBackend, TesseractBackend};
+use crate::plate_detection::plate_association::PlateAssociator;
+use crate::plate_detection::plate_format::{PlateFormat, PositionalFormat, RegexFormat};
+use crate::plate_detection::plate_rectifier::PlateRectifier;
+use crate::plate_detection::plate_voting::PlateVoting;
+use crate::plate_detection::regions::RegionsOfInterest;
+use crate::plate_detection::rtp_sink::RtpSink;
+use crate::plate_detection::tracker::{Track, Tracker};
+use crate::plate_detection::video_reader::{FrameClock, VideoReader, VideoSource};
+use crate::plate_detection::{CarWithText, Detection};
+
+/// A node of the car plates graph, typed by the processor it wraps.
+enum GraphNode {
+    Source(SourceNode<WriteChannel1<Mat>>),
+    Detector(Node<ReadChannel1<Mat>, WriteChannel1<Vec<Detection>>>),
+    Cascade(Node<ReadChannel2<Mat, Vec<Detection>>, WriteChannel1<Vec<Detection>>>),
+    Ocr(Node<ReadChannel2<Mat, Vec<Detection>>, WriteChannel1<Vec<CarWithText>>>),
+    Tracker(Node<ReadChannel1<Vec<Detection>>, WriteChannel1<Vec<Track>>>),
+    Voting(Node<ReadChannel2<Vec<Track>, Vec<CarWithText>>, WriteChannel1<Vec<CarWithText>>>),
+    Association(
+        Node<ReadChannel2<Vec<Detection>, Vec<CarWithText>>, WriteChannel1<Vec<CarWithText>>>,
+    ),
+    Render(Node<ReadChannel3<Vec<Detection>, Vec<CarWithText>, Mat>, WriteChannel1<Mat>>),
+    Rtp(TerminalNode<ReadChannel1<Mat>>),
+}
+
+fn make_synchronizer(config: &SynchronizerConfig) -> Box<dyn PacketSynchronizer> {
+    match config {
+        SynchronizerConfig::Timestamp => Box::new(TimestampSynchronizer::default()),
+        SynchronizerConfig::RealTime {
+            tolerance_ns,
+            wait_all,
+            buffering,
+        } => Box::new(RealTimeSynchronizer::new(
+            *tolerance_ns as u128,
+            *wait_all,
+            *buffering,
+        )),
+    }
+}
+
+/// A started graph together with the end of stream notifications of its sources.
+pub struct Pipeline {
+    pub graph: Graph,
+    pub end_of_stream: Receiver<()>,
+    pub sources: usize,
+}
+
+impl Pipeline {
+    /// Blocks until every source has reached the end of its stream.
+    pub fn wait_end_of_stream(&self) {
+        for _ in 0..self.sources {
+            if self.end_of_stream.recv().is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn make_regions(config: &RegionsConfig) -> RegionsOfInterest {
+    let polygon = |points: &Vec<[i32; 2]>| -> Vec<Point> {
+        points.iter().map(|[x, y]| Point::new(*x, *y)).collect()
+    };
+    let mut regions = RegionsOfInterest::default();
+    for zone in &config.include {
+        regions = regions.include(polygon(zone));
+    }
+    for zone in &config.exclude {
+        regions = regions.exclude(polygon(zone));
+    }
+    regions
+}
+
+fn make_formats(configs: &[PlateFormatConfig]) -> Result<Vec<Box<dyn PlateFormat>>, String> {
+    let mut formats: Vec<Box<dyn PlateFormat>> = vec![];
+    for config in configs {
+        match config {
+            PlateFormatConfig::Uk => formats.push(Box::new(PositionalFormat::uk())),
+            PlateFormatConfig::Pattern { name, pattern } => {
+                formats.push(Box::new(PositionalFormat::new(name, pattern)?))
+            }
+            PlateFormatConfig::Regex { name, regex } => formats.push(Box::new(
+                RegexFormat::new(name, regex).map_err(|e| e.to_string())?,
+            )),
+        }
+    }
+    Ok(formats)
+}
+
+fn make_ocr_backend(config: &OcrEngineConfig) -> Result<Box<dyn OcrBackend>, RustedPipeError> {
+    match config {
+        OcrEngineConfig::Tesseract => Ok(Box::new(TesseractBackend::default())),
+        OcrEngineConfig::Crnn {
+            model,
+            alphabet,
+            width,
+            height,
+            output,
+            device,
+        } => Ok(Box::new(CrnnBackend::from_onnx(
+            model,
+            alphabet,
+            Size::new(*width, *height),
+            (*output).into(),
+            (*device).into(),
+        )?)),
+    }
+}
+
+fn plate_detector(network: &PlateNetworkConfig) -> Result<ObjectDetector, RustedPipeError> {
+    ObjectDetector::plate_detector_from_onnx(
+        network.device.into(),
+        network.model.as_deref().unwrap_or("models/plate_best.onnx"),
+        network.input_size,
+        network.yolo_version.into(),
+        network.engine.into(),
+        network.thresholds.apply(DetectionConfig::plates()),
+    )
+}
+
+fn make_node(
+    config: &NodeConfig,
+    regions: &RegionsOfInterest,
+    end_of_stream: &Sender<()>,
+) -> Result<GraphNode, ConfigError> {
+    let id = config.name.clone();
+    let queue = &config.queue;
+    let synch = make_synchronizer(&config.synchronizer);
+    let processor_error = |e: RustedPipeError| ConfigError::Processor {
+        node: config.name.clone(),
+        error: format!("{:?}", e),
+    };
+
+    // Every node that is not a source shares the same queue and synchronization setup.
+    macro_rules! node {
+        ($node_type:ident, $processor:expr) => {
+            $node_type::create_common(
+                id,
+                Box::new($processor),
+                queue.block_full,
+                queue.channel_buffer_size,
+                queue.process_buffer_size,
+                synch,
+                queue.blocking_read,
+            )
+        };
+    }
+
+    let node = match &config.processor {
+        ProcessorConfig::VideoReader {
+            source,
+            do_loop,
+            fps,
+            media_time,
+            output_size,
+        } => {
+            let clock = if *media_time {
+                FrameClock::Media
+            } else {
+                FrameClock::WallClock
+            };
+            let reader = VideoReader::new(VideoSource::parse(source), *do_loop, *fps)
+                .map_err(processor_error)?
+                .with_clock(clock)
+                .with_output_size(Size::new(output_size.width, output_size.height))
+                .notify_end_of_stream(end_of_stream.clone())
+                .with_node_id(&config.name);
+            GraphNode::Source(SourceNode::create_common(id, Box::new(reader)))
+        }
+        ProcessorConfig::ImageReader {
+            path,
+            fps,
+            timestamps,
+            output_size,
+        } => {
+            let mut reader = ImageSequenceReader::new(path, *fps)
+                .map_err(processor_error)?
+                .with_output_size(Size::new(output_size.width, output_size.height));
+            if let Some(timestamps) = timestamps {
+                reader = reader
+                    .with_timestamps(timestamps)
+                    .map_err(processor_error)?;
+            }
+            let reader = reader
+                .notify_end_of_stream(end_of_stream.clone())
+                .with_node_id(&config.name);
+            GraphNode::Source(SourceNode::create_common(id, Box::new(reader)))
+        }
+        ProcessorConfig::CarDetector { device, thresholds } => {
+            let detector = ObjectDetector::car_detector(
+                (*device).into(),
+                thresholds.apply(DetectionConfig::cars()),
+            )
+            .map_err(processor_error)?
+            .with_regions(regions.clone())
+            .with_node_id(&config.name);
+            GraphNode::Detector(node!(Node, detector))
+        }
+        ProcessorConfig::PlateDetector { network, tiling } => {
+            let mut detector = plate_detector(network)
+                .map_err(processor_error)?
+                .with_regions(regions.clone())
+                .with_node_id(&config.name);
+            if let Some(tiling) = tiling {
+                detector = detector
+                    .with_tiling((*tiling).into())
+                    .map_err(processor_error)?;
+            }
+            GraphNode::Detector(node!(Node, detector))
+        }
+        ProcessorConfig::PlateCascade { network, padding } => {
+            let detector = plate_detector(network)
+                .map_err(processor_error)?
+                .with_regions(regions.clone());
+            let cascade = CascadePlateDetector::new(detector, *padding).with_node_id(&config.name);
+            GraphNode::Cascade(node!(Node, cascade))
+        }
+        ProcessorConfig::Ocr {
+            engine,
+            workers,
+            formats,
+            min_confidence,
+            rectify,
+            preprocessing,
+            shapes,
+        } => {
+            let workers = (0..(*workers).max(1))
+                .map(|_| make_ocr_backend(engine))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(processor_error)?;
+            let formats = make_formats(formats).map_err(|error| ConfigError::Processor {
+                node: config.name.clone(),
+                error,
+            })?;
+            let mut reader = DnnOcrReader::new(workers)
+                .with_node_id(&config.name)
+                .with_regions(regions.clone())
+                .with_formats(formats)
+                .with_min_confidence(*min_confidence)
+                .with_preprocessing(preprocessing.iter().map(Into::into).collect());
+            if !shapes.is_empty() {
+                reader = reader.with_shapes(shapes.iter().map(Into::into).collect());
+            }
+            if let Some(rectify) = rectify {
+                let mut rectifier = PlateRectifier::new(Size::new(rectify.width, rectify.height));
+                if let Some(debug_dir) = &rectify.debug_dir {
+                    rectifier = rectifier.with_debug_dir(debug_dir);
+                }
+                reader = reader.with_rectifier(rectifier);
+            }
+            GraphNode::Ocr(node!(Node, reader))
+        }
+        ProcessorConfig::Tracker {
+            max_age,
+            min_hits,
+            iou_threshold,
+        } => GraphNode::Tracker(node!(
+            Node,
+            Tracker::new(*max_age, *min_hits, *iou_threshold).with_node_id(&config.name)
+        )),
+        ProcessorConfig::PlateAssociation { min_coverage } => GraphNode::Association(node!(
+            Node,
+            PlateAssociator::new(*min_coverage).with_node_id(&config.name)
+        )),
+        ProcessorConfig::PlateVoting { min_votes } => GraphNode::Voting(node!(
+            Node,
+            PlateVoting::new(*min_votes).with_node_id(&config.name)
+        )),
+        ProcessorConfig::BoundingBoxRender {
+            save_to_file,
+            draw_regions,
+        } => {
+            let mut render = if *save_to_file {
+                BoundingBoxRender::with_save_to_file()
+            } else {
+                BoundingBoxRender::default()
+            };
+            render = render.with_node_id(&config.name);
+            if *draw_regions {
+                render = render.draw_regions(regions.clone());
+            }
+            GraphNode::Render(node!(Node, render))
+        }
+        ProcessorConfig::RtpSink {
+            fps,
+            host,
+            port,
+            frame_size,
+        } => GraphNode::Rtp(node!(
+            TerminalNode,
+            RtpSink::with_size(
+                *fps,
+                host,
+                *port,
+                frame_size.width as usize,
+                frame_size.height as usize
+            )
+        )),
+    };
+    Ok(node)
+}
+
+macro_rules! link_or_err {
+    ($from:expr, $to:expr) => {
+        link($from, $to).map_err(|e| ConfigError::Link(format!("{:?}", e)))
+    };
+}
+
+/// Links the single output of `from` to the input `to_channel` of `to`.
+/// Channel types have already been checked by `PipelineConfig::validate`.
+fn link_nodes(
+    from: &mut GraphNode,
+    to: &mut GraphNode,
+    to_channel: &str,
+) -> Result<(), ConfigError> {
+    let frame = match from {
+        GraphNode::Source(from) => Some(from.write_channel.writer.c1()),
+        GraphNode::Render(from) => Some(from.write_channel.writer.c1()),
+        _ => None,
+    };
+    if let Some(frame) = frame {
+        return match (to, to_channel) {
+            (GraphNode::Detector(node), "image") => {
+                link_or_err!(frame, node.read_channel.channels.write().unwrap().c1())
+            }
+            (GraphNode::Ocr(node), "image") => {
+                link_or_err!(frame, node.read_channel.channels.write().unwrap().c1())
+            }
+            (GraphNode::Cascade(node), "image") => {
+                link_or_err!(frame, node.read_channel.channels.write().unwrap().c1())
+            }
+            (GraphNode::Render(node), "image") => {
+                link_or_err!(frame, node.read_channel.channels.write().unwrap().c3())
+            }
+            (GraphNode::Rtp(node), "image") => {
+                link_or_err!(frame, node.read_channel.channels.write().unwrap().c1())
+            }
+            _ => Err(ConfigError::Link(format!("No frame input {to_channel}"))),
+        };
+    }
+
+    let boxes = match from {
+        GraphNode::Detector(from) => Some(from.write_channel.writer.c1()),
+        GraphNode::Cascade(from) => Some(from.write_channel.writer.c1()),
+        _ => None,
+    };
+    if let Some(boxes) = boxes {
+        return match (to, to_channel) {
+            (GraphNode::Ocr(node), "plates") => {
+                link_or_err!(boxes, node.read_channel.channels.write().unwrap().c2())
+            }
+            (GraphNode::Cascade(node), "cars") => {
+                link_or_err!(boxes, node.read_channel.channels.write().unwrap().c2())
+            }
+            (GraphNode::Association(node), "cars") => {
+                link_or_err!(boxes, node.read_channel.channels.write().unwrap().c1())
+            }
+            (GraphNode::Tracker(node), "cars") => {
+                link_or_err!(boxes, node.read_channel.channels.write().unwrap().c1())
+            }
+            (GraphNode::Render(node), "cars") => {
+                link_or_err!(boxes, node.read_channel.channels.write().unwrap().c1())
+            }
+            _ => Err(ConfigError::Link(format!("No boxes input {to_channel}"))),
+        };
+    }
+
+    if let GraphNode::Tracker(from) = from {
+        let tracks = from.write_channel.writer.c1();
+        return match (to, to_channel) {
+            (GraphNode::Voting(node), "tracks") => {
+                link_or_err!(tracks, node.read_channel.channels.write().unwrap().c1())
+            }
+            _ => Err(ConfigError::Link(format!("No tracks input {to_channel}"))),
+        };
+    }
+
+    let plates = match from {
+        GraphNode::Ocr(from) => from.write_channel.writer.c1(),
+        GraphNode::Association(from) => from.write_channel.writer.c1(),
+        GraphNode::Voting(from) => from.write_channel.writer.c1(),
+        _ => return Err(ConfigError::Link("Node has no output".to_string())),
+    };
+    match (to, to_channel) {
+        (GraphNode::Association(node), "plates") => {
+            link_or_err!(plates, node.read_channel.channels.write().unwrap().c2())
+        }
+        (GraphNode::Voting(node), "plates") => {
+            link_or_err!(plates, node.read_channel.channels.write().unwrap().c2())
+        }
+        (GraphNode::Render(node), "plates") => {
+            link_or_err!(plates, node.read_channel.channels.write().unwrap().c2())
+        }
+        _ => Err(ConfigError::Link(format!("No plates input {to_channel}"))),
+    }
+}
+
+/// Creates every node described in the configuration, links them and starts the graph.
+pub fn build_graph(config: &PipelineConfig) -> Result<Pipeline, ConfigError> {
+    config.validate()?;
+
+    let (end_of_stream_s, end_of_stream_r) = unbounded();
+
+    let regions = make_regions(&config.regions);
+    let mut nodes = HashMap::new();
+    for node in &config.nodes {
+        nodes.insert(
+            node.name.clone(),
+            make_node(node, &regions, &end_of_stream_s)?,
+        );
+    }
+
+    for link_config in &config.links {
+        let (from_name, _) = link_config.from_endpoint()?;
+        let (to_name, to_channel) = link_config.to_endpoint()?;
+
+        let mut from = nodes
+            .remove(from_name)
+            .ok_or_else(|| ConfigError::UnknownNode(from_name.to_string()))?;
+        let result = match nodes.get_mut(to_name) {
+            Some(to) => link_nodes(&mut from, to, to_channel),
+            None => Err(ConfigError::UnknownNode(to_name.to_string())),
+        };
+        nodes.insert(from_name.to_string(), from);
+        result?;
+    }
+
+    let metrics = if config.prometheus {
+        Metrics::builder().with_prometheus(&default_prometheus_address())
+    } else {
+        Metrics::no_metrics()
+    };
+    let mut graph = Graph::new(metrics);
+
+    // Start downstream nodes first so that no data is produced before they are ready.
+    let mut sources = vec![];
+    for (_, node) in nodes {
+        match node {
+            GraphNode::Source(node) => sources.push(node),
+            GraphNode::Detector(node) => graph.start_node(node),
+            GraphNode::Cascade(node) => graph.start_node(node),
+            GraphNode::Tracker(node) => graph.start_node(node),
+            GraphNode::Association(node) => graph.start_node(node),
+            GraphNode::Voting(node) => graph.start_node(node),
+            GraphNode::Ocr(node) => graph.start_node(node),
+            GraphNode::Render(node) => graph.start_node(node),
+            GraphNode::Rtp(node) => graph.start_terminal_node(node),
+        }
+    }
+    let source_count = sources.len();
+    for source in sources {
+        graph.start_source_node(source);
+    }
+
+    Ok(Pipeline {
+        graph,
+        end_of_stream: end_of_stream_r,
+        sources: source_count,
+    })
+}
@@ -0,0 +1,971 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::ConfigError;
+use crate::plate_detection::dnn_ocr::PlateShape;
+use crate::plate_detection::inference::{DevicePolicy, InferenceEngine};
+use crate::plate_detection::object_detector::{DetectionConfig, Tiling, YoloVersion};
+use crate::plate_detection::ocr_backend::CrnnOutput;
+use crate::plate_detection::plate_preprocessing::PreprocessingStep;
+use crate::plate_detection::video_reader::SAMPLE_VIDEO;
+
+/// Data type carried by a channel. Two channels can only be linked when they carry the same type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelType {
+    /// `Mat`
+    Frame,
+    /// `Vec<Detection>`
+    Boxes,
+    /// `Vec<CarWithText>`
+    Plates,
+    /// `Vec<Track>`
+    Tracks,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub nodes: Vec<NodeConfig>,
+    #[serde(default)]
+    pub links: Vec<LinkConfig>,
+    #[serde(default)]
+    pub prometheus: bool,
+    /// Stop the graph after this time. When missing, the graph runs until every source ends.
+    #[serde(default)]
+    pub run_for_ms: Option<u64>,
+    /// Zones applied to the detectors and the OCR.
+    #[serde(default)]
+    pub regions: RegionsConfig,
+}
+
+/// Polygons as lists of `[x, y]` frame pixels.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegionsConfig {
+    #[serde(default)]
+    pub include: Vec<Vec<[i32; 2]>>,
+    #[serde(default)]
+    pub exclude: Vec<Vec<[i32; 2]>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    pub name: String,
+    pub processor: ProcessorConfig,
+    #[serde(default)]
+    pub synchronizer: SynchronizerConfig,
+    #[serde(default)]
+    pub queue: QueueConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, tag = "kind", rename_all = "snake_case")]
+pub enum ProcessorConfig {
+    VideoReader {
+        /// File path, URI, V4L2 device index or GStreamer pipeline.
+        #[serde(default = "default_video_source")]
+        source: String,
+        #[serde(default)]
+        do_loop: bool,
+        fps: usize,
+        /// Version frames with their position in the video instead of the wall clock.
+        #[serde(default)]
+        media_time: bool,
+        /// Size of the frames sent downstream, letterboxed to keep the aspect ratio.
+        #[serde(default)]
+        output_size: FrameSize,
+    },
+    ImageReader {
+        /// Directory or glob pattern of the images.
+        path: String,
+        fps: usize,
+        /// Optional `file_name,timestamp_ms` CSV file used to version the images.
+        #[serde(default)]
+        timestamps: Option<String>,
+        #[serde(default)]
+        output_size: FrameSize,
+    },
+    CarDetector {
+        #[serde(default)]
+        device: DeviceConfig,
+        #[serde(default)]
+        thresholds: ThresholdsConfig,
+    },
+    PlateDetector {
+        #[serde(flatten)]
+        network: PlateNetworkConfig,
+        /// Run the model on overlapping tiles of the frame instead of the whole frame.
+        #[serde(default)]
+        tiling: Option<TilingConfig>,
+    },
+    /// Plate detector running inside the car boxes of an upstream car detector.
+    PlateCascade {
+        #[serde(flatten)]
+        network: PlateNetworkConfig,
+        /// Fraction of the car size added on every side of the car box.
+        #[serde(default = "default_cascade_padding")]
+        padding: f32,
+    },
+    Ocr {
+        #[serde(default)]
+        engine: OcrEngineConfig,
+        /// Independent OCR instances reading the plates of a frame in parallel.
+        #[serde(default = "default_ocr_workers")]
+        workers: usize,
+        /// Plate formats the reads are corrected to, in order. Reads matching none are rejected.
+        /// Every read is accepted when empty.
+        #[serde(default)]
+        formats: Vec<PlateFormatConfig>,
+        /// Reads with a lower mean confidence, between 0 and 1, are dropped.
+        #[serde(default)]
+        min_confidence: f32,
+        /// Straightens the plates before reading them.
+        #[serde(default)]
+        rectify: Option<RectifyConfig>,
+        /// Steps applied in order to the plate crops before reading them.
+        #[serde(default)]
+        preprocessing: Vec<PreprocessingConfig>,
+        /// Plates read, by class and aspect ratio. Single line plates 3 to 4 times as wide as high
+        /// when empty.
+        #[serde(default)]
+        shapes: Vec<PlateShapeConfig>,
+    },
+    /// Follows the cars across frames.
+    Tracker {
+        /// Frames a track survives without a matching detection.
+        #[serde(default = "default_max_age")]
+        max_age: u32,
+        /// Consecutive matches needed to confirm a track.
+        #[serde(default = "default_min_hits")]
+        min_hits: u32,
+        #[serde(default = "default_iou_threshold")]
+        iou_threshold: f32,
+    },
+    /// Matches the OCR reads with the cars containing them.
+    PlateAssociation {
+        /// Minimum fraction of the plate box inside the car box.
+        #[serde(default = "default_min_coverage")]
+        min_coverage: f32,
+    },
+    /// Replaces the text read in each frame by the consensus of the reads of the car track.
+    PlateVoting {
+        /// Reads of a track needed before its plate is emitted.
+        #[serde(default = "default_min_votes")]
+        min_votes: usize,
+    },
+    BoundingBoxRender {
+        #[serde(default)]
+        save_to_file: bool,
+        /// Outline the `regions` of the pipeline on the frames.
+        #[serde(default)]
+        draw_regions: bool,
+    },
+    RtpSink {
+        fps: usize,
+        host: String,
+        port: usize,
+        /// Must match the size of the incoming frames.
+        #[serde(default)]
+        frame_size: FrameSize,
+    },
+}
+
+impl ProcessorConfig {
+    /// Named read channels of the processor, in the order of its `ReadChannelN`.
+    pub fn inputs(&self) -> &'static [(&'static str, ChannelType)] {
+        match self {
+            ProcessorConfig::VideoReader { .. } | ProcessorConfig::ImageReader { .. } => &[],
+            ProcessorConfig::CarDetector { .. } | ProcessorConfig::PlateDetector { .. } => {
+                &[("image", ChannelType::Frame)]
+            }
+            ProcessorConfig::PlateCascade { .. } => {
+                &[("image", ChannelType::Frame), ("cars", ChannelType::Boxes)]
+            }
+            ProcessorConfig::Ocr { .. } => &[
+                ("image", ChannelType::Frame),
+                ("plates", ChannelType::Boxes),
+            ],
+            ProcessorConfig::Tracker { .. } => &[("cars", ChannelType::Boxes)],
+            ProcessorConfig::PlateAssociation { .. } => &[
+                ("cars", ChannelType::Boxes),
+                ("plates", ChannelType::Plates),
+            ],
+            ProcessorConfig::PlateVoting { .. } => &[
+                ("tracks", ChannelType::Tracks),
+                ("plates", ChannelType::Plates),
+            ],
+            ProcessorConfig::BoundingBoxRender { .. } => &[
+                ("cars", ChannelType::Boxes),
+                ("plates", ChannelType::Plates),
+                ("image", ChannelType::Frame),
+            ],
+            ProcessorConfig::RtpSink { .. } => &[("image", ChannelType::Frame)],
+        }
+    }
+
+    /// Checks the parameters that are out of range.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ProcessorConfig::VideoReader { fps, .. }
+            | ProcessorConfig::ImageReader { fps, .. }
+            | ProcessorConfig::RtpSink { fps, .. }
+                if *fps == 0 =>
+            {
+                Err("fps must be positive".to_string())
+            }
+            ProcessorConfig::CarDetector { thresholds, .. } => thresholds.validate(),
+            ProcessorConfig::PlateDetector { network, .. }
+            | ProcessorConfig::PlateCascade { network, .. } => network.thresholds.validate(),
+            ProcessorConfig::Ocr { min_confidence, .. } => {
+                check_fraction("min_confidence", *min_confidence)
+            }
+            ProcessorConfig::Tracker { iou_threshold, .. } => {
+                check_fraction("iou_threshold", *iou_threshold)
+            }
+            ProcessorConfig::PlateAssociation { min_coverage } => {
+                check_fraction("min_coverage", *min_coverage)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Named write channel of the processor, `None` for terminal processors.
+    pub fn output(&self) -> Option<(&'static str, ChannelType)> {
+        match self {
+            ProcessorConfig::VideoReader { .. } | ProcessorConfig::ImageReader { .. } => {
+                Some(("frame", ChannelType::Frame))
+            }
+            ProcessorConfig::CarDetector { .. }
+            | ProcessorConfig::PlateDetector { .. }
+            | ProcessorConfig::PlateCascade { .. } => Some(("boxes", ChannelType::Boxes)),
+            ProcessorConfig::Tracker { .. } => Some(("tracks", ChannelType::Tracks)),
+            ProcessorConfig::Ocr { .. }
+            | ProcessorConfig::PlateAssociation { .. }
+            | ProcessorConfig::PlateVoting { .. } => Some(("plates", ChannelType::Plates)),
+            ProcessorConfig::BoundingBoxRender { .. } => Some(("frame", ChannelType::Frame)),
+            ProcessorConfig::RtpSink { .. } => None,
+        }
+    }
+}
+
+/// Plate model and how to run it, shared by the plate detector and the cascade. Flattened into
+/// their processors, which reject the unknown fields instead.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlateNetworkConfig {
+    #[serde(default)]
+    pub device: DeviceConfig,
+    #[serde(default)]
+    pub thresholds: ThresholdsConfig,
+    /// ONNX model, `models/plate_best.onnx` when missing.
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default = "default_plate_input_size")]
+    pub input_size: i32,
+    #[serde(default)]
+    pub yolo_version: YoloVersionConfig,
+    #[serde(default)]
+    pub engine: EngineConfig,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum YoloVersionConfig {
+    V3,
+    #[default]
+    V5,
+    V8,
+}
+
+impl From<YoloVersionConfig> for YoloVersion {
+    fn from(version: YoloVersionConfig) -> Self {
+        match version {
+            YoloVersionConfig::V3 => YoloVersion::V3,
+            YoloVersionConfig::V5 => YoloVersion::V5,
+            YoloVersionConfig::V8 => YoloVersion::V8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, tag = "kind", rename_all = "snake_case")]
+pub enum PlateFormatConfig {
+    /// Current UK plates, `AA00 AAA`.
+    Uk,
+    /// One character per position: `A` for a letter, `0` for a digit and `?` for either. Spaces
+    /// are ignored.
+    Pattern { name: String, pattern: String },
+    /// Regular expression matching the whole read, without spaces.
+    Regex { name: String, regex: String },
+}
+
+/// Text recognizer of an OCR node.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, tag = "kind", rename_all = "snake_case")]
+pub enum OcrEngineConfig {
+    /// `models/licence.traineddata`.
+    #[default]
+    Tesseract,
+    /// ONNX CRNN decoded by CTC, run by the OpenCV DNN module.
+    Crnn {
+        model: String,
+        /// Characters of the model classes, following the CTC blank.
+        #[serde(default = "default_crnn_alphabet")]
+        alphabet: String,
+        #[serde(default = "default_crnn_width")]
+        width: i32,
+        #[serde(default = "default_crnn_height")]
+        height: i32,
+        /// Whether the model ends with a softmax.
+        #[serde(default)]
+        output: CrnnOutputConfig,
+        #[serde(default)]
+        device: DeviceConfig,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrnnOutputConfig {
+    #[default]
+    Logits,
+    Probabilities,
+}
+
+impl From<CrnnOutputConfig> for CrnnOutput {
+    fn from(output: CrnnOutputConfig) -> Self {
+        match output {
+            CrnnOutputConfig::Logits => CrnnOutput::Logits,
+            CrnnOutputConfig::Probabilities => CrnnOutput::Probabilities,
+        }
+    }
+}
+
+/// Plates an OCR node reads, see `PlateShape`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlateShapeConfig {
+    /// Class of the plate detector, any when missing.
+    #[serde(default)]
+    pub class_id: Option<i32>,
+    pub min_ratio: f32,
+    pub max_ratio: f32,
+    #[serde(default = "default_plate_lines")]
+    pub lines: i32,
+}
+
+impl From<&PlateShapeConfig> for PlateShape {
+    fn from(shape: &PlateShapeConfig) -> Self {
+        PlateShape {
+            class_id: shape.class_id,
+            min_ratio: shape.min_ratio,
+            max_ratio: shape.max_ratio,
+            lines: shape.lines,
+        }
+    }
+}
+
+/// Plate crop preprocessing step, see `PreprocessingStep`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, tag = "kind", rename_all = "snake_case")]
+pub enum PreprocessingConfig {
+    Upscale {
+        factor: f64,
+    },
+    Clahe {
+        #[serde(default = "default_clahe_clip_limit")]
+        clip_limit: f64,
+        #[serde(default = "default_clahe_tile_size")]
+        tile_size: i32,
+    },
+    OtsuThreshold,
+    AdaptiveThreshold {
+        #[serde(default = "default_threshold_block_size")]
+        block_size: i32,
+        #[serde(default)]
+        c: f64,
+    },
+    Sharpen {
+        #[serde(default)]
+        delta: f64,
+    },
+    Invert,
+    Padding {
+        pixels: i32,
+    },
+}
+
+impl From<&PreprocessingConfig> for PreprocessingStep {
+    fn from(step: &PreprocessingConfig) -> Self {
+        match *step {
+            PreprocessingConfig::Upscale { factor } => PreprocessingStep::Upscale(factor),
+            PreprocessingConfig::Clahe {
+                clip_limit,
+                tile_size,
+            } => PreprocessingStep::Clahe {
+                clip_limit,
+                tile_size,
+            },
+            PreprocessingConfig::OtsuThreshold => PreprocessingStep::OtsuThreshold,
+            PreprocessingConfig::AdaptiveThreshold { block_size, c } => {
+                PreprocessingStep::AdaptiveThreshold { block_size, c }
+            }
+            PreprocessingConfig::Sharpen { delta } => PreprocessingStep::Sharpen { delta },
+            PreprocessingConfig::Invert => PreprocessingStep::Invert,
+            PreprocessingConfig::Padding { pixels } => PreprocessingStep::Padding(pixels),
+        }
+    }
+}
+
+/// Size of the straightened plates, 4:1 like the detected plate boxes by default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RectifyConfig {
+    #[serde(default = "default_rectified_width")]
+    pub width: i32,
+    #[serde(default = "default_rectified_height")]
+    pub height: i32,
+    /// Folder the straightened plates are written to.
+    #[serde(default)]
+    pub debug_dir: Option<String>,
+}
+
+/// Device a detector should run on, see `DevicePolicy`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceConfig {
+    #[default]
+    Auto,
+    Cpu,
+    Cuda,
+    Opencl,
+}
+
+impl From<DeviceConfig> for DevicePolicy {
+    fn from(device: DeviceConfig) -> Self {
+        match device {
+            DeviceConfig::Auto => DevicePolicy::Auto,
+            DeviceConfig::Cpu => DevicePolicy::Cpu,
+            DeviceConfig::Cuda => DevicePolicy::Cuda,
+            DeviceConfig::Opencl => DevicePolicy::OpenCl,
+        }
+    }
+}
+
+/// Runtime executing the plate model.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EngineConfig {
+    #[default]
+    Opencv,
+    Tract,
+}
+
+impl From<EngineConfig> for InferenceEngine {
+    fn from(engine: EngineConfig) -> Self {
+        match engine {
+            EngineConfig::Opencv => InferenceEngine::OpenCv,
+            EngineConfig::Tract => InferenceEngine::Tract,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TilingConfig {
+    #[serde(default = "default_plate_input_size")]
+    pub tile_size: i32,
+    #[serde(default = "default_tile_overlap")]
+    pub overlap: i32,
+}
+
+impl From<TilingConfig> for Tiling {
+    fn from(tiling: TilingConfig) -> Self {
+        Tiling {
+            tile_size: tiling.tile_size,
+            overlap: tiling.overlap,
+        }
+    }
+}
+
+fn default_max_age() -> u32 {
+    5
+}
+
+fn default_min_hits() -> u32 {
+    3
+}
+
+fn default_iou_threshold() -> f32 {
+    0.3
+}
+
+fn default_min_votes() -> usize {
+    5
+}
+
+fn default_min_coverage() -> f32 {
+    0.6
+}
+
+fn default_cascade_padding() -> f32 {
+    0.1
+}
+
+fn default_crnn_alphabet() -> String {
+    "0123456789abcdefghijklmnopqrstuvwxyz".to_string()
+}
+
+fn default_crnn_width() -> i32 {
+    100
+}
+
+fn default_crnn_height() -> i32 {
+    32
+}
+
+fn default_plate_lines() -> i32 {
+    1
+}
+
+fn default_ocr_workers() -> usize {
+    1
+}
+
+fn default_clahe_clip_limit() -> f64 {
+    2.0
+}
+
+fn default_clahe_tile_size() -> i32 {
+    8
+}
+
+fn default_threshold_block_size() -> i32 {
+    15
+}
+
+fn default_rectified_width() -> i32 {
+    200
+}
+
+fn default_rectified_height() -> i32 {
+    50
+}
+
+fn default_tile_overlap() -> i32 {
+    128
+}
+
+fn default_plate_input_size() -> i32 {
+    640
+}
+
+/// Overrides of the detector defaults, see `DetectionConfig`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThresholdsConfig {
+    pub confidence: Option<f32>,
+    pub score: Option<f32>,
+    pub nms_score: Option<f32>,
+    pub nms_iou: Option<f32>,
+    pub classes: Option<Vec<i32>>,
+    pub top_k: Option<i32>,
+}
+
+impl ThresholdsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let thresholds = [
+            ("confidence", self.confidence),
+            ("score", self.score),
+            ("nms_score", self.nms_score),
+            ("nms_iou", self.nms_iou),
+        ];
+        for (name, value) in thresholds {
+            if let Some(value) = value {
+                check_fraction(name, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, mut config: DetectionConfig) -> DetectionConfig {
+        config.confidence = self.confidence.unwrap_or(config.confidence);
+        config.score = self.score.unwrap_or(config.score);
+        config.nms_score = self.nms_score.unwrap_or(config.nms_score);
+        config.nms_iou = self.nms_iou.unwrap_or(config.nms_iou);
+        config.classes = self.classes.clone().unwrap_or(config.classes);
+        config.top_k = self.top_k.unwrap_or(config.top_k);
+        config
+    }
+}
+
+fn check_fraction(name: &str, value: f32) -> Result<(), String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(format!("{name} must be between 0 and 1, not {value}"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrameSize {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for FrameSize {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, tag = "kind", rename_all = "snake_case")]
+pub enum SynchronizerConfig {
+    #[default]
+    Timestamp,
+    RealTime {
+        tolerance_ns: u64,
+        #[serde(default = "default_true")]
+        wait_all: bool,
+        #[serde(default)]
+        buffering: bool,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueueConfig {
+    #[serde(default = "default_true")]
+    pub block_full: bool,
+    #[serde(default = "default_buffer_size")]
+    pub channel_buffer_size: usize,
+    #[serde(default = "default_buffer_size")]
+    pub process_buffer_size: usize,
+    #[serde(default = "default_true")]
+    pub blocking_read: bool,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            block_full: true,
+            channel_buffer_size: default_buffer_size(),
+            process_buffer_size: default_buffer_size(),
+            blocking_read: true,
+        }
+    }
+}
+
+fn default_video_source() -> String {
+    SAMPLE_VIDEO.to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_buffer_size() -> usize {
+    1000
+}
+
+/// A link between an output and an input, both written as `node.channel`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinkConfig {
+    pub from: String,
+    pub to: String,
+}
+
+impl LinkConfig {
+    pub fn from_endpoint(&self) -> Result<(&str, &str), ConfigError> {
+        split_endpoint(&self.from)
+    }
+
+    pub fn to_endpoint(&self) -> Result<(&str, &str), ConfigError> {
+        split_endpoint(&self.to)
+    }
+}
+
+fn split_endpoint(endpoint: &str) -> Result<(&str, &str), ConfigError> {
+    endpoint
+        .split_once('.')
+        .ok_or_else(|| ConfigError::UnknownChannel {
+            node: endpoint.to_string(),
+            channel: String::new(),
+        })
+}
+
+impl PipelineConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(e.to_string()))?;
+        Self::from_yaml(&content)
+    }
+
+    pub fn from_yaml(content: &str) -> Result<Self, ConfigError> {
+        let config: PipelineConfig =
+            serde_yaml::from_str(content).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that the parameters of every node are in range, that the graph has a source, that
+    /// every link connects existing channels of the same type and that every input of every node
+    /// is fed by exactly one output.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut nodes = HashMap::new();
+        for node in &self.nodes {
+            if nodes.insert(node.name.as_str(), node).is_some() {
+                return Err(ConfigError::DuplicateNode(node.name.clone()));
+            }
+            node.processor
+                .validate()
+                .map_err(|error| ConfigError::InvalidParameter {
+                    node: node.name.clone(),
+                    error,
+                })?;
+        }
+        if !self
+            .nodes
+            .iter()
+            .any(|node| node.processor.inputs().is_empty())
+        {
+            return Err(ConfigError::NoSource);
+        }
+
+        let mut linked_inputs = HashSet::new();
+        for link in &self.links {
+            let (from_node, from_channel) = link.from_endpoint()?;
+            let (to_node, to_channel) = link.to_endpoint()?;
+
+            let from_config = nodes
+                .get(from_node)
+                .ok_or_else(|| ConfigError::UnknownNode(from_node.to_string()))?;
+            let to_config = nodes
+                .get(to_node)
+                .ok_or_else(|| ConfigError::UnknownNode(to_node.to_string()))?;
+
+            let from_type = match from_config.processor.output() {
+                Some((name, channel_type)) if name == from_channel => channel_type,
+                _ => {
+                    return Err(ConfigError::UnknownChannel {
+                        node: from_node.to_string(),
+                        channel: from_channel.to_string(),
+                    })
+                }
+            };
+            let to_type = to_config
+                .processor
+                .inputs()
+                .iter()
+                .find(|(name, _)| *name == to_channel)
+                .map(|(_, channel_type)| *channel_type)
+                .ok_or_else(|| ConfigError::UnknownChannel {
+                    node: to_node.to_string(),
+                    channel: to_channel.to_string(),
+                })?;
+
+            if from_type != to_type {
+                return Err(ConfigError::IncompatibleChannels {
+                    from: link.from.clone(),
+                    to: link.to.clone(),
+                });
+            }
+
+            // Read channels are 1 to 1.
+            if !linked_inputs.insert((to_node, to_channel)) {
+                return Err(ConfigError::InputLinkedTwice {
+                    node: to_node.to_string(),
+                    channel: to_channel.to_string(),
+                });
+            }
+        }
+
+        for node in &self.nodes {
+            for (channel, _) in node.processor.inputs() {
+                if !linked_inputs.contains(&(node.name.as_str(), *channel)) {
+                    return Err(ConfigError::DanglingInput {
+                        node: node.name.clone(),
+                        channel: channel.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const READER: &str = "
+  - name: video_input
+    processor: { kind: video_reader, fps: 10 }";
+
+    const DETECTOR: &str = "
+  - name: detector
+    processor: { kind: car_detector }";
+
+    const READER_TO_DETECTOR: &str = "
+links:
+  - { from: video_input.frame, to: detector.image }";
+
+    fn pipeline(nodes: &[&str], links: &str) -> String {
+        format!("nodes:{}\n{links}", nodes.concat())
+    }
+
+    #[test]
+    fn parses_the_shipped_pipelines() {
+        let pipelines = [
+            include_str!("../../pipelines/cars_cascade.yaml"),
+            include_str!("../../pipelines/cars_images.yaml"),
+            include_str!("../../pipelines/cars_offline.yaml"),
+            include_str!("../../pipelines/cars_realtime.yaml"),
+        ];
+        for content in pipelines {
+            if let Err(error) = PipelineConfig::from_yaml(content) {
+                panic!("{error}");
+            }
+        }
+    }
+
+    #[test]
+    fn parses_a_minimal_pipeline() {
+        let config =
+            PipelineConfig::from_yaml(&pipeline(&[READER, DETECTOR], READER_TO_DETECTOR)).unwrap();
+        assert_eq!(config.nodes.len(), 2);
+        assert!(matches!(
+            config.nodes[1].synchronizer,
+            SynchronizerConfig::Timestamp
+        ));
+    }
+
+    #[test]
+    fn rejects_duplicate_nodes() {
+        let result =
+            PipelineConfig::from_yaml(&pipeline(&[READER, DETECTOR, DETECTOR], READER_TO_DETECTOR));
+        assert!(matches!(result, Err(ConfigError::DuplicateNode(node)) if node == "detector"));
+    }
+
+    #[test]
+    fn rejects_links_to_unknown_nodes() {
+        let links = "
+links:
+  - { from: video_input.frame, to: detector.image }
+  - { from: detector.boxes, to: ocr.plates }";
+        let result = PipelineConfig::from_yaml(&pipeline(&[READER, DETECTOR], links));
+        assert!(matches!(result, Err(ConfigError::UnknownNode(node)) if node == "ocr"));
+    }
+
+    #[test]
+    fn rejects_links_to_unknown_channels() {
+        let links = "
+links:
+  - { from: video_input.image, to: detector.image }";
+        let result = PipelineConfig::from_yaml(&pipeline(&[READER, DETECTOR], links));
+        assert!(matches!(
+            result,
+            Err(ConfigError::UnknownChannel { node, channel })
+                if node == "video_input" && channel == "image"
+        ));
+    }
+
+    #[test]
+    fn rejects_dangling_inputs() {
+        let result = PipelineConfig::from_yaml(&pipeline(&[READER, DETECTOR], ""));
+        assert!(matches!(
+            result,
+            Err(ConfigError::DanglingInput { node, channel })
+                if node == "detector" && channel == "image"
+        ));
+    }
+
+    #[test]
+    fn rejects_pipelines_without_source() {
+        let result = PipelineConfig::from_yaml(&pipeline(&[DETECTOR], ""));
+        assert!(matches!(result, Err(ConfigError::NoSource)));
+    }
+
+    #[test]
+    fn rejects_zero_fps() {
+        let reader = "
+  - name: video_input
+    processor: { kind: video_reader, fps: 0 }";
+        let result = PipelineConfig::from_yaml(&pipeline(&[reader, DETECTOR], READER_TO_DETECTOR));
+        assert!(matches!(
+            result,
+            Err(ConfigError::InvalidParameter { node, .. }) if node == "video_input"
+        ));
+    }
+
+    #[test]
+    fn rejects_thresholds_out_of_range() {
+        let detector = "
+  - name: detector
+    processor: { kind: car_detector, thresholds: { nms_iou: 1.5 } }";
+        let result = PipelineConfig::from_yaml(&pipeline(&[READER, detector], READER_TO_DETECTOR));
+        assert!(matches!(
+            result,
+            Err(ConfigError::InvalidParameter { node, .. }) if node == "detector"
+        ));
+
+        let detector = "
+  - name: detector
+    processor: { kind: plate_detector, thresholds: { confidence: -0.1 } }";
+        let result = PipelineConfig::from_yaml(&pipeline(&[READER, detector], READER_TO_DETECTOR));
+        assert!(matches!(result, Err(ConfigError::InvalidParameter { .. })));
+
+        let ocr = "
+  - name: ocr
+    processor: { kind: ocr, min_confidence: 80 }";
+        let links = "
+links:
+  - { from: video_input.frame, to: detector.image }
+  - { from: video_input.frame, to: ocr.image }
+  - { from: detector.boxes, to: ocr.plates }";
+        let result = PipelineConfig::from_yaml(&pipeline(&[READER, DETECTOR, ocr], links));
+        assert!(matches!(
+            result,
+            Err(ConfigError::InvalidParameter { node, .. }) if node == "ocr"
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let detector = "
+  - name: detector
+    processor: { kind: car_detector, treshold: 0.5 }";
+        let result = PipelineConfig::from_yaml(&pipeline(&[READER, detector], READER_TO_DETECTOR));
+        assert!(matches!(result, Err(ConfigError::Parse(_))));
+
+        // The plate network fields are flattened into the processor.
+        let detector = "
+  - name: detector
+    processor: { kind: plate_detector, model: plates.onnx, input_sise: 320 }";
+        let result = PipelineConfig::from_yaml(&pipeline(&[READER, detector], READER_TO_DETECTOR));
+        assert!(matches!(result, Err(ConfigError::Parse(_))));
+
+        let detector = "
+  - name: detector
+    processor: { kind: plate_detector, model: plates.onnx, input_size: 320 }";
+        let config =
+            PipelineConfig::from_yaml(&pipeline(&[READER, detector], READER_TO_DETECTOR)).unwrap();
+        match &config.nodes[1].processor {
+            ProcessorConfig::PlateDetector { network, .. } => {
+                assert_eq!(network.model.as_deref(), Some("plates.onnx"));
+                assert_eq!(network.input_size, 320);
+            }
+            processor => panic!("Unexpected processor {processor:?}"),
+        }
+    }
+}
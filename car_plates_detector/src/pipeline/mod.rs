@@ -0,0 +1,53 @@
+pub mod builder;
+pub mod config;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String),
+    Parse(String),
+    DuplicateNode(String),
+    InvalidParameter { node: String, error: String },
+    NoSource,
+    UnknownNode(String),
+    UnknownChannel { node: String, channel: String },
+    IncompatibleChannels { from: String, to: String },
+    DanglingInput { node: String, channel: String },
+    InputLinkedTwice { node: String, channel: String },
+    Link(String),
+    Processor { node: String, error: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Cannot read pipeline file: {e}"),
+            ConfigError::Parse(e) => write!(f, "Cannot parse pipeline file: {e}"),
+            ConfigError::DuplicateNode(node) => write!(f, "Node {node} is defined twice"),
+            ConfigError::InvalidParameter { node, error } => {
+                write!(f, "Invalid parameter of node {node}: {error}")
+            }
+            ConfigError::NoSource => write!(f, "The pipeline has no video or image reader"),
+            ConfigError::UnknownNode(node) => write!(f, "Unknown node {node}"),
+            ConfigError::UnknownChannel { node, channel } => {
+                write!(f, "Node {node} has no channel named {channel}")
+            }
+            ConfigError::IncompatibleChannels { from, to } => {
+                write!(f, "Cannot link {from} to {to}: channel types differ")
+            }
+            ConfigError::DanglingInput { node, channel } => {
+                write!(f, "Input {node}.{channel} is not linked to any output")
+            }
+            ConfigError::InputLinkedTwice { node, channel } => {
+                write!(f, "Input {node}.{channel} is linked more than once")
+            }
+            ConfigError::Link(e) => write!(f, "Cannot link channels: {e}"),
+            ConfigError::Processor { node, error } => {
+                write!(f, "Cannot create processor for {node}: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
@@ -5,13 +5,12 @@ use std::time::UNIX_EPOCH;
 use opencv::core::Point;
 use opencv::core::Rect;
 use opencv::core::Scalar;
-use opencv::core::Size;
-use opencv::core::Vector;
 use opencv::imgproc::put_text;
 use opencv::imgproc::FONT_HERSHEY_PLAIN;
 use opencv::imgproc::LINE_8;
 use opencv::imgproc::{rectangle, LineTypes};
 use opencv::prelude::Mat;
+use opencv::prelude::MatTraitConst;
 
 use lazy_static::lazy_static;
 use opencv::videoio::VideoWriter;
@@ -25,11 +24,18 @@ use rusted_pipe::graph::processor::Processor;
 use rusted_pipe::graph::processor::ProcessorWriter;
 use rusted_pipe::RustedPipeError;
 
+use crate::plate_detection::count_frame;
+use crate::plate_detection::regions::RegionsOfInterest;
 use crate::plate_detection::CarWithText;
+use crate::plate_detection::Detection;
 
 pub struct BoundingBoxRender {
+    node_id: String,
+    save_to_file: bool,
     writer: Option<VideoWriter>,
     metrics: &'static Histogram,
+    started: SystemTime,
+    regions: Option<RegionsOfInterest>,
 }
 lazy_static! {
     static ref METRICS: Histogram =
@@ -38,25 +44,37 @@ lazy_static! {
 }
 impl BoundingBoxRender {
     pub fn with_save_to_file() -> Self {
+        // The video file is created with the size of the first frame.
         Self {
-            writer: Some(
-                VideoWriter::new(
-                    "output.avi",
-                    VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap(),
-                    25.0,
-                    Size::new(1280, 720),
-                    true,
-                )
-                .unwrap(),
-            ),
+            node_id: "bbox_render".to_string(),
+            save_to_file: true,
+            writer: None,
             metrics: &METRICS,
+            started: SystemTime::now(),
+            regions: None,
         }
     }
 
+    /// Outlines the zones of `regions` on every frame.
+    pub fn draw_regions(mut self, regions: RegionsOfInterest) -> Self {
+        self.regions = Some(regions);
+        self
+    }
+
+    /// Id of the graph node, labelling the frames counted by the renderer.
+    pub fn with_node_id(mut self, node_id: &str) -> Self {
+        self.node_id = node_id.to_string();
+        self
+    }
+
     pub fn default() -> Self {
         Self {
+            node_id: "bbox_render".to_string(),
+            save_to_file: false,
             writer: None,
             metrics: &METRICS,
+            started: SystemTime::now(),
+            regions: None,
         }
     }
 }
@@ -71,7 +89,7 @@ impl Drop for BoundingBoxRender {
 }
 
 impl Processor for BoundingBoxRender {
-    type INPUT = ReadChannel3<Vector<Rect>, Vec<CarWithText>, Mat>;
+    type INPUT = ReadChannel3<Vec<Detection>, Vec<CarWithText>, Mat>;
     type OUTPUT = WriteChannel1<Mat>;
     fn handle(
         &mut self,
@@ -88,7 +106,7 @@ impl Processor for BoundingBoxRender {
         let mut image = input.c3_owned().unwrap();
 
         let mut plates = Vec::<CarWithText>::new();
-        let mut bboxes = Vector::<Rect>::new();
+        let mut bboxes = Vec::<Detection>::new();
         if let Some(bboxes_packet) = input.c1_owned() {
             bboxes = bboxes_packet.data;
         }
@@ -96,28 +114,47 @@ impl Processor for BoundingBoxRender {
             plates = plates_packet.data;
         }
 
+        if let Some(regions) = self.regions.as_ref() {
+            regions.draw(&mut image.data);
+        }
+
         let color = Scalar::from((255.0, 0.0, 0.0));
         let color_red = Scalar::from((0.0, 255.0, 0.0));
         let thikness_px = 2;
 
-        for bbox_i in 0..bboxes.len() {
-            let bbox = bboxes.get(bbox_i).unwrap();
-
+        for bbox in bboxes.iter() {
             rectangle(
                 &mut image.data,
-                bbox,
+                bbox.rect,
                 color,
                 thikness_px,
                 LineTypes::LINE_4 as i32,
                 0,
             )
             .unwrap();
+            put_text(
+                &mut image.data,
+                &format!("{} {:.2}", bbox.label, bbox.confidence),
+                Point::new(bbox.rect.x, bbox.rect.y + bbox.rect.height - 3),
+                FONT_HERSHEY_PLAIN,
+                1.5,
+                color,
+                2,
+                LINE_8,
+                false,
+            )
+            .unwrap();
         }
 
-        for plate_i in 0..plates.len() {
-            let plate = plates.get(plate_i).unwrap();
-            let plate_text = plate.plate.as_ref().unwrap();
-            let header = Rect::new(plate.car.x, plate.car.y - 60, plate.car.width, 60);
+        for plate in plates.iter() {
+            let plate_text = match plate.text.as_ref() {
+                Some(text) if !plate.rejected => text,
+                _ => continue,
+            };
+            let plate_rect = plate.plate.rect;
+            // The text goes on top of the car when the plate was matched with one.
+            let text_rect = plate.car.as_ref().map(|car| car.rect).unwrap_or(plate_rect);
+            let header = Rect::new(text_rect.x, text_rect.y - 60, text_rect.width, 60);
             rectangle(
                 &mut image.data,
                 header,
@@ -129,7 +166,7 @@ impl Processor for BoundingBoxRender {
             .unwrap();
             rectangle(
                 &mut image.data,
-                plate.car,
+                plate_rect,
                 color_red,
                 thikness_px,
                 LineTypes::LINE_4 as i32,
@@ -139,7 +176,7 @@ impl Processor for BoundingBoxRender {
             put_text(
                 &mut image.data,
                 plate_text,
-                Point::new(plate.car.x, plate.car.y - 3),
+                Point::new(text_rect.x, text_rect.y - 3),
                 FONT_HERSHEY_PLAIN,
                 4.0,
                 Scalar::from((255.0, 255.0, 255.0)),
@@ -150,6 +187,18 @@ impl Processor for BoundingBoxRender {
             .unwrap();
         }
 
+        if self.save_to_file && self.writer.is_none() {
+            self.writer = Some(
+                VideoWriter::new(
+                    "output.avi",
+                    VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap(),
+                    25.0,
+                    image.data.size().unwrap(),
+                    true,
+                )
+                .unwrap(),
+            );
+        }
         if let Some(writer) = self.writer.as_mut() {
             writer.write(&image.data).unwrap();
         }
@@ -159,11 +208,18 @@ impl Processor for BoundingBoxRender {
             .c1()
             .write(image.data, &image.version)
             .expect("Cannot write to output buffer");
-        let pipeline_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
-            - Duration::from_nanos(image.version.timestamp_ns as u64);
-
-        println!("Pipe time {}", pipeline_time.as_secs_f64());
-        self.metrics.observe(pipeline_time.as_secs_f64());
+        count_frame(&self.node_id);
+        // Frames versioned with their media time are older than the renderer,
+        // the pipeline time only makes sense for wall clock versions.
+        let frame_time = UNIX_EPOCH + Duration::from_nanos(image.version.timestamp_ns as u64);
+        if frame_time >= self.started {
+            let pipeline_time = SystemTime::now()
+                .duration_since(frame_time)
+                .unwrap_or_default();
+
+            println!("Pipe time {}", pipeline_time.as_secs_f64());
+            self.metrics.observe(pipeline_time.as_secs_f64());
+        }
         Ok(())
     }
 }
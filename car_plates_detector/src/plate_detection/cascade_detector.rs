@@ -0,0 +1,150 @@
+use opencv::core::Point;
+use opencv::core::Rect;
+use opencv::prelude::Mat;
+use opencv::prelude::MatTraitConst;
+use rusted_pipe::channels::typed_read_channel::ReadChannel2;
+use rusted_pipe::channels::typed_write_channel::WriteChannel1;
+use rusted_pipe::graph::processor::Processor;
+use rusted_pipe::graph::processor::ProcessorWriter;
+use rusted_pipe::packet::typed::ReadChannel2PacketSet;
+use rusted_pipe::RustedPipeError;
+
+use super::count_frame;
+use super::object_detector::ObjectDetector;
+use super::Detection;
+
+/// Runs the plate detector only inside the car boxes of the frame instead of the whole frame.
+/// Every plate is emitted in frame coordinates, with the box of its car as `parent`. A plate found
+/// in several car boxes is only emitted once.
+pub struct CascadePlateDetector {
+    plates: ObjectDetector,
+    padding: f32,
+}
+
+impl CascadePlateDetector {
+    /// `padding` grows each car box by this fraction of its size on every side, so that plates
+    /// on the border of a tight car box are not cut.
+    pub fn new(plates: ObjectDetector, padding: f32) -> Self {
+        Self {
+            plates: plates.with_node_id("plate_cascade"),
+            padding,
+        }
+    }
+
+    /// Id of the graph node, labelling the frames counted and the inference time of the cascade.
+    pub fn with_node_id(mut self, node_id: &str) -> Self {
+        self.plates = self.plates.with_node_id(node_id);
+        self
+    }
+
+    fn crop_rect(&self, car: &Rect, cols: i32, rows: i32) -> Rect {
+        let pad_x = (car.width as f32 * self.padding) as i32;
+        let pad_y = (car.height as f32 * self.padding) as i32;
+
+        let left = (car.x - pad_x).max(0);
+        let top = (car.y - pad_y).max(0);
+        let right = (car.x + car.width + pad_x).min(cols);
+        let bottom = (car.y + car.height + pad_y).min(rows);
+        Rect::new(left, top, right - left, bottom - top)
+    }
+}
+
+/// Car of `plate`: the car box containing the centre of the plate, the one covering most of the
+/// plate when the boxes of several cars do.
+fn parent_car(plate: &Rect, cars: &[Rect]) -> Option<Rect> {
+    let centre = Point::new(plate.x + plate.width / 2, plate.y + plate.height / 2);
+    cars.iter()
+        .max_by_key(|car| (car.contains(centre), (*plate & **car).area()))
+        .copied()
+}
+
+unsafe impl Send for CascadePlateDetector {}
+unsafe impl Sync for CascadePlateDetector {}
+
+impl Processor for CascadePlateDetector {
+    type INPUT = ReadChannel2<Mat, Vec<Detection>>;
+    type OUTPUT = WriteChannel1<Vec<Detection>>;
+    fn handle(
+        &mut self,
+        mut input: ReadChannel2PacketSet<Mat, Vec<Detection>>,
+        mut output: ProcessorWriter<Self::OUTPUT>,
+    ) -> Result<(), RustedPipeError> {
+        let image_packet = input.c1_owned().unwrap();
+        println!("Cascade Detection {}", image_packet.version.timestamp_ns);
+        let image = &image_packet.data;
+        let cars = input.c2_owned().unwrap();
+
+        let cars: Vec<Rect> = cars.data.iter().map(|car| car.rect).collect();
+        let mut out = vec![];
+        for car in &cars {
+            let crop = self.crop_rect(car, image.cols(), image.rows());
+            if crop.width <= 0 || crop.height <= 0 {
+                continue;
+            }
+
+            let car_image = image.apply_1(crop).unwrap();
+            for mut plate in self.plates.detect(&car_image)? {
+                plate.rect.x += crop.x;
+                plate.rect.y += crop.y;
+                out.push(plate);
+            }
+        }
+        // Plates in the overlap of two padded car boxes are found in both, the merged plate
+        // belongs to the car it is in rather than to the car it was first found in.
+        let mut out = self.plates.merge(out);
+        for plate in out.iter_mut() {
+            plate.parent = parent_car(&plate.rect, &cars);
+        }
+        self.plates.retain_in_regions(&mut out);
+
+        output
+            .writer
+            .c1()
+            .write(out, &image_packet.version)
+            .unwrap();
+        count_frame(self.plates.node_id());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The car in front hides the back of the car behind it.
+    const BEHIND: Rect = Rect {
+        x: 100,
+        y: 100,
+        width: 400,
+        height: 300,
+    };
+    const FRONT: Rect = Rect {
+        x: 300,
+        y: 200,
+        width: 400,
+        height: 300,
+    };
+
+    #[test]
+    fn parent_car_contains_the_plate_centre() {
+        // Also inside the padded box of the car behind.
+        let plate = Rect::new(420, 410, 80, 20);
+        assert_eq!(parent_car(&plate, &[BEHIND, FRONT]), Some(FRONT));
+        assert_eq!(parent_car(&plate, &[FRONT, BEHIND]), Some(FRONT));
+
+        let plate = Rect::new(200, 350, 80, 20);
+        assert_eq!(parent_car(&plate, &[BEHIND, FRONT]), Some(BEHIND));
+        assert_eq!(parent_car(&plate, &[FRONT, BEHIND]), Some(BEHIND));
+    }
+
+    #[test]
+    fn parent_car_covers_most_of_the_plate() {
+        // Centred in both cars, most of the plate is in the car in front.
+        let plate = Rect::new(450, 300, 80, 20);
+        assert_eq!(parent_car(&plate, &[BEHIND, FRONT]), Some(FRONT));
+        // Centred in none, in the padding below the car behind.
+        let plate = Rect::new(150, 395, 80, 20);
+        assert_eq!(parent_car(&plate, &[BEHIND, FRONT]), Some(BEHIND));
+        assert_eq!(parent_car(&plate, &[]), None);
+    }
+}
@@ -1,69 +1,221 @@
+use super::count_frame;
+use super::ocr_backend::OcrBackend;
+use super::ocr_backend::PlateRead;
+use super::ocr_backend::TesseractBackend;
+use super::plate_format::PlateFormat;
+use super::plate_preprocessing::PreprocessingStep;
+use super::plate_rectifier::PlateRectifier;
+use super::regions::RegionsOfInterest;
 use super::CarWithText;
+use super::Detection;
 
-use leptess::tesseract;
-use leptess::tesseract::TessApi;
-use opencv::core::Point;
+use lazy_static::lazy_static;
 use opencv::core::Rect;
 
-use opencv::core::Vector;
-
 use opencv::core::CV_32F;
 use opencv::imgproc::cvt_color;
 
 use opencv::imgproc::COLOR_BGR2GRAY;
 
-use opencv::imgproc::filter_2d;
 use opencv::prelude::Mat;
 
 use opencv::prelude::MatTraitConst;
 
-use opencv::prelude::MatTraitManual;
+use prometheus::exponential_buckets;
+use prometheus::linear_buckets;
+use prometheus::register_histogram;
+use prometheus::register_histogram_vec;
+use prometheus::Histogram;
+use prometheus::HistogramVec;
 use rusted_pipe::channels::typed_read_channel::ReadChannel2;
 use rusted_pipe::channels::typed_write_channel::WriteChannel1;
 use rusted_pipe::graph::processor::Processor;
 use rusted_pipe::graph::processor::ProcessorWriter;
 use rusted_pipe::packet::typed::ReadChannel2PacketSet;
 use rusted_pipe::RustedPipeError;
-use std::ffi::CString;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+lazy_static! {
+    static ref OCR_CONFIDENCE: Histogram = register_histogram!(
+        "ocr_confidence",
+        "Mean confidence of the plate reads, before filtering",
+        linear_buckets(0.1, 0.1, 10).unwrap()
+    )
+    .expect("Cannot create OCR confidence histogram");
+    static ref OCR_TIME: HistogramVec = register_histogram_vec!(
+        "ocr_plate_time_ms",
+        "Time spent reading each plate, or each line of the multi-line plates",
+        &["backend"],
+        // 1 ms to 2 s.
+        exponential_buckets(1.0, 2.0, 12).unwrap()
+    )
+    .expect("Cannot create OCR timer");
+}
+
+/// Plates the OCR reads: the plates of class `class_id`, any when `None`, whose width over height
+/// ratio is between `min_ratio` and `max_ratio`. The `lines` of a plate are read one by one, from
+/// the top, and their texts put end to end.
+#[derive(Clone, Debug)]
+pub struct PlateShape {
+    pub class_id: Option<i32>,
+    pub min_ratio: f32,
+    pub max_ratio: f32,
+    pub lines: i32,
+}
+
+impl PlateShape {
+    pub fn single_line() -> Self {
+        Self {
+            class_id: None,
+            min_ratio: 3.0,
+            max_ratio: 4.0,
+            lines: 1,
+        }
+    }
+
+    fn accepts(&self, plate: &Detection) -> bool {
+        let ratio = plate.rect.width as f32 / plate.rect.height as f32;
+        self.class_id
+            .map_or(true, |class_id| class_id == plate.class_id)
+            && ratio > self.min_ratio
+            && ratio < self.max_ratio
+    }
+}
 
 pub struct DnnOcrReader {
-    ocr: TessApi,
-    deblur: bool,
+    node_id: String,
+    /// Read the plates of a frame in parallel.
+    workers: Vec<Box<dyn OcrBackend>>,
+    preprocessing: Vec<PreprocessingStep>,
+    regions: RegionsOfInterest,
+    formats: Vec<Box<dyn PlateFormat>>,
+    min_confidence: f32,
+    rectifier: Option<PlateRectifier>,
+    shapes: Vec<PlateShape>,
 }
 
 impl Default for DnnOcrReader {
     fn default() -> Self {
-        let mut api = tesseract::TessApi::new(Some("models"), "licence").unwrap();
-        let data_path_cstr = CString::new("models").unwrap();
-        let lang = CString::new("licence").unwrap();
-
-        api.raw
-            .init_4(Some(data_path_cstr.as_ref()), Some(lang.as_ref()), 1)
-            .unwrap();
-        api.raw
-            .set_variable(
-                &CString::new("tessedit_char_whitelist").unwrap(),
-                &CString::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789").unwrap(),
-            )
-            .unwrap();
-        api.raw
-            .set_variable(
-                &CString::new("tessedit_pageseg_mode").unwrap(),
-                &CString::new("7").unwrap(),
-            )
-            .unwrap();
+        Self::new(vec![Box::new(TesseractBackend::default())])
+    }
+}
 
+impl DnnOcrReader {
+    /// Needs at least one worker. Every worker is an independent OCR, usually of the same kind.
+    pub fn new(workers: Vec<Box<dyn OcrBackend>>) -> Self {
+        assert!(!workers.is_empty(), "The OCR needs at least one worker");
         Self {
-            ocr: api,
-            deblur: false,
+            node_id: "ocr_detector".to_string(),
+            workers,
+            preprocessing: vec![],
+            regions: RegionsOfInterest::default(),
+            formats: vec![],
+            min_confidence: 0.0,
+            rectifier: None,
+            shapes: vec![PlateShape::single_line()],
         }
     }
-}
 
-impl DnnOcrReader {
-    
+    /// Id of the graph node, labelling the frames counted by the OCR.
+    pub fn with_node_id(mut self, node_id: &str) -> Self {
+        self.node_id = node_id.to_string();
+        self
+    }
 
-    fn reshape_plate(&self, image: &Mat, rect: &Rect) -> Mat {
+    /// Only reads the plates centred in the active zones of `regions`.
+    pub fn with_regions(mut self, regions: RegionsOfInterest) -> Self {
+        self.regions = regions;
+        self
+    }
+
+    /// Reads are corrected to the first of `formats` they match, and rejected when they match none.
+    pub fn with_formats(mut self, formats: Vec<Box<dyn PlateFormat>>) -> Self {
+        self.formats = formats;
+        self
+    }
+
+    /// Drops the reads whose mean confidence, between 0 and 1, is below `min_confidence`.
+    pub fn with_min_confidence(mut self, min_confidence: f32) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    /// Applies `steps`, in order, to the plate crops before reading them.
+    pub fn with_preprocessing(mut self, steps: Vec<PreprocessingStep>) -> Self {
+        self.preprocessing = steps;
+        self
+    }
+
+    /// Reads the plates straightened by `rectifier` instead of their shrunk boxes.
+    pub fn with_rectifier(mut self, rectifier: PlateRectifier) -> Self {
+        self.rectifier = Some(rectifier);
+        self
+    }
+
+    /// Only reads the plates of one of `shapes`, with the first shape they match. Single line plates
+    /// with a 3 to 4 ratio are read by default.
+    pub fn with_shapes(mut self, shapes: Vec<PlateShape>) -> Self {
+        self.shapes = shapes;
+        self
+    }
+
+    /// Cuts the plate in `lines` bands of the same height.
+    fn split_lines(plate: Mat, lines: i32) -> Vec<Mat> {
+        if lines <= 1 || plate.rows() < lines {
+            return vec![plate];
+        }
+        let height = plate.rows() / lines;
+        (0..lines)
+            .map(|line| {
+                let band = Rect::new(0, line * height, plate.cols(), height);
+                // Make it contiguous
+                plate.apply_1(band).unwrap().clone()
+            })
+            .collect()
+    }
+
+    /// Reads the crops on all the workers at once, each taking the next crop when done with the
+    /// previous one. The reads are in the order of the crops, `None` when the OCR failed.
+    fn read_plates(&mut self, crops: Vec<Mat>) -> Vec<Option<PlateRead>> {
+        if self.workers.len() == 1 || crops.len() < 2 {
+            let worker = self.workers[0].as_mut();
+            return crops.iter().map(|crop| read_plate(worker, crop)).collect();
+        }
+
+        let mut plate_reads = vec![None; crops.len()];
+        let queue = Mutex::new(crops.into_iter().enumerate());
+        thread::scope(|scope| {
+            let handles: Vec<_> =
+                self.workers
+                    .iter_mut()
+                    .map(|worker| {
+                        let queue = &queue;
+                        scope.spawn(move || {
+                            let mut worker_reads = vec![];
+                            loop {
+                                let next = queue.lock().unwrap().next();
+                                match next {
+                                    Some((index, crop)) => worker_reads
+                                        .push((index, read_plate(worker.as_mut(), &crop))),
+                                    None => return worker_reads,
+                                }
+                            }
+                        })
+                    })
+                    .collect();
+            for handle in handles {
+                for (index, plate_read) in handle.join().unwrap() {
+                    plate_reads[index] = plate_read;
+                }
+            }
+        });
+        plate_reads
+    }
+
+    /// `name` identifies the plate in the debug output of the rectifier.
+    fn reshape_plate(&self, image: &Mat, rect: &Rect, lines: i32, name: &str) -> Mat {
         let mut image_2f = Mat::default();
         image.convert_to(&mut image_2f, CV_32F, 1.0, 0.0).unwrap();
         let mut rect_smaller = rect.clone();
@@ -74,26 +226,60 @@ impl DnnOcrReader {
         rect_smaller.width -= (rect_smaller.width as f32 * 0.12) as i32;
         rect_smaller.height -= (rect_smaller.height as f32 * 0.24) as i32;
 
-        let cropped = image.apply_1(rect_smaller).unwrap();
-        if self.deblur {
-            let mut processed = Mat::default();
-            let kernel = Mat::from_slice_2d(&[[-1, -1, -1], [-1, 9, -1], [-1, -1, -1]]).unwrap();
-
-            filter_2d(
-                &cropped,
-                &mut processed,
-                -1,
-                &kernel,
-                Point::new(0, 0),
-                0.0,
-                -22,
-            )
-            .unwrap();
+        // The rectifier searches the plate outline in a slightly larger box, as the detected box
+        // can cut its corners.
+        let rectified = self.rectifier.as_ref().and_then(|rectifier| {
+            let padded = Rect::new(
+                rect.x - rect.width / 10,
+                rect.y - rect.height / 10,
+                rect.width + rect.width / 5,
+                rect.height + rect.height / 5,
+            ) & Rect::new(0, 0, image.cols(), image.rows());
+            rectifier.rectify(&image.apply_1(padded).unwrap(), lines, name)
+        });
+        let cropped = match rectified {
+            Some(warped) => warped,
+            None => image.apply_1(rect_smaller).unwrap(),
+        };
+        // Make it contiguous
+        let mut plate = cropped.clone();
+        for step in &self.preprocessing {
+            match step.apply(&plate) {
+                Ok(processed) => plate = processed,
+                Err(e) => println!("OCR preprocessing {:?} failed: {:?}", step, e),
+            }
+        }
+        return plate;
+    }
+}
+
+/// Puts the reads of the lines of a plate end to end, `None` when a line could not be read.
+/// The confidence is the mean of the confidences of the lines.
+fn join_lines(line_reads: Vec<Option<PlateRead>>) -> Option<PlateRead> {
+    let lines = line_reads.len();
+    let mut plate_read = PlateRead::default();
+    for line_read in line_reads {
+        let line_read = line_read?;
+        plate_read.text.push_str(&line_read.text);
+        plate_read.confidence += line_read.confidence / lines as f32;
+        plate_read
+            .char_confidences
+            .extend(line_read.char_confidences);
+    }
+    Some(plate_read)
+}
 
-            return processed;
-        } else {
-            // Make it contiguous
-            return cropped.clone();
+fn read_plate(ocr: &mut dyn OcrBackend, crop: &Mat) -> Option<PlateRead> {
+    let read_start = Instant::now();
+    let plate_read = ocr.read(crop);
+    OCR_TIME
+        .with_label_values(&[ocr.name()])
+        .observe(read_start.elapsed().as_secs_f64() * 1000.0);
+    match plate_read {
+        Ok(plate_read) => Some(plate_read),
+        Err(e) => {
+            println!("OCR {} failed: {:?}", ocr.name(), e);
+            None
         }
     }
 }
@@ -102,11 +288,11 @@ unsafe impl Send for DnnOcrReader {}
 unsafe impl Sync for DnnOcrReader {}
 
 impl Processor for DnnOcrReader {
-    type INPUT = ReadChannel2<Mat, Vector<Rect>>;
+    type INPUT = ReadChannel2<Mat, Vec<Detection>>;
     type OUTPUT = WriteChannel1<Vec<CarWithText>>;
     fn handle(
         &mut self,
-        mut input: ReadChannel2PacketSet<Mat, Vector<Rect>>,
+        mut input: ReadChannel2PacketSet<Mat, Vec<Detection>>,
         mut output: ProcessorWriter<Self::OUTPUT>,
     ) -> Result<(), RustedPipeError> {
         let image_packet = input.c1_owned().unwrap();
@@ -115,30 +301,66 @@ impl Processor for DnnOcrReader {
         let mut grey = Mat::default();
         cvt_color(image, &mut grey, COLOR_BGR2GRAY, 0).unwrap();
 
-        let mut out_rect: Vec<CarWithText> = vec![];
+        // Plates read and the number of lines of each, the crops holding every line.
+        let mut accepted: Vec<(Detection, usize)> = vec![];
+        let mut crops: Vec<Mat> = vec![];
         let plates = input.c2_owned().unwrap();
-        for rect in plates.data {
-            let ratio = rect.width as f32 / rect.height as f32;
-            if rect.x > 2
+        for (index, plate) in plates.data.into_iter().enumerate() {
+            let rect = plate.rect;
+            let inside = rect.x > 2
                 && rect.y > 2
                 && rect.x <= image.cols() - 2
                 && rect.y <= image.rows() - 2
-                && ratio > 3.0
-                && ratio < 4.0
-            {
-                let mut cropped = self.reshape_plate(&grey, &rect);
-
-                let cols = cropped.cols();
-                let rows = cropped.rows();
-                self.ocr
-                    .raw
-                    .set_image(&cropped.data_bytes_mut().unwrap(), cols, rows, 1, cols)
-                    .unwrap();
-                let result = self.ocr.get_utf8_text().unwrap();
-                let result_processed = result.trim().replace(" ", "");
-                println!("OCR {:?}, {:?}", result_processed, cropped);
-                out_rect.push(CarWithText::new(Some(String::from(result_processed)), rect));
+                && self.regions.accepts(&rect);
+            if !inside {
+                continue;
+            }
+            if let Some(shape) = self.shapes.iter().find(|shape| shape.accepts(&plate)) {
+                let name = format!("{}_{}", image_packet.version.timestamp_ns, index);
+                let lines = Self::split_lines(
+                    self.reshape_plate(&grey, &rect, shape.lines, &name),
+                    shape.lines,
+                );
+                accepted.push((plate, lines.len()));
+                crops.extend(lines);
+            }
+        }
+
+        let mut out_rect: Vec<CarWithText> = vec![];
+        let mut line_reads = self.read_plates(crops).into_iter();
+        for (plate, lines) in accepted {
+            let plate_read = join_lines(line_reads.by_ref().take(lines).collect());
+            let plate_read = match plate_read {
+                Some(plate_read) => plate_read,
+                None => continue,
+            };
+            OCR_CONFIDENCE.observe(plate_read.confidence as f64);
+            println!(
+                "OCR {:?} {:.2}, {:?}",
+                plate_read.text, plate_read.confidence, plate.rect
+            );
+            if plate_read.confidence < self.min_confidence {
+                continue;
+            }
+            let result_processed = plate_read.text;
+            let mut read =
+                CarWithText::new(Some(result_processed.clone()), plate_read.confidence, plate);
+            read.char_confidences = plate_read.char_confidences;
+            if !self.formats.is_empty() {
+                let matched = self.formats.iter().find_map(|format| {
+                    format
+                        .read(&result_processed)
+                        .map(|text| (text, format.name().to_string()))
+                });
+                match matched {
+                    Some((text, format)) => {
+                        read.text = Some(text);
+                        read.format = Some(format);
+                    }
+                    None => read.rejected = true,
+                }
             }
+            out_rect.push(read);
         }
 
         output
@@ -146,6 +368,7 @@ impl Processor for DnnOcrReader {
             .c1()
             .write(out_rect, &image_packet.version)
             .unwrap();
+        count_frame(&self.node_id);
 
         Ok(())
     }
@@ -0,0 +1,234 @@
+use opencv::core::Scalar;
+use opencv::core::Size;
+
+use opencv::imgcodecs::imread;
+use opencv::imgcodecs::IMREAD_COLOR;
+use opencv::prelude::Mat;
+use opencv::prelude::MatTraitConst;
+
+use crossbeam::channel::Sender;
+use rusted_pipe::channels::typed_write_channel::WriteChannel1;
+use rusted_pipe::graph::processor::ProcessorWriter;
+use rusted_pipe::graph::processor::SourceProcessor;
+use rusted_pipe::DataVersion;
+use rusted_pipe::RustedPipeError;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::count_frame;
+use super::letterbox::Letterbox;
+
+const IMAGE_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// Reads a sorted sequence of still images and sends them as frames, like `VideoReader` does for videos.
+///
+/// Frames are versioned with their index in the sequence at the given fps, or with the
+/// timestamps read from a sidecar CSV file (see `with_timestamps`).
+pub struct ImageSequenceReader {
+    node_id: String,
+    images: Vec<PathBuf>,
+    next: usize,
+    timestamps: Option<HashMap<String, u128>>,
+    fps_control: Instant,
+    fps_wait: Duration,
+    fps: u64,
+    end_of_stream: Option<Sender<()>>,
+    output_size: Size,
+}
+
+fn read_error(reason: String) -> RustedPipeError {
+    RustedPipeError::ProcessorError(reason)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Lists the images of a directory, or the images matching a glob pattern, in name order.
+fn list_images(pattern: &str) -> Result<Vec<PathBuf>, RustedPipeError> {
+    let mut images: Vec<PathBuf> = if Path::new(pattern).is_dir() {
+        fs::read_dir(pattern)
+            .map_err(|e| read_error(format!("Cannot list {pattern}: {e}")))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect()
+    } else {
+        glob::glob(pattern)
+            .map_err(|e| read_error(format!("Invalid pattern {pattern}: {e}")))?
+            .filter_map(|entry| entry.ok())
+            .collect()
+    };
+    images.retain(|path| path.is_file() && is_image(path));
+    images.sort();
+
+    if images.is_empty() {
+        return Err(read_error(format!("No images found in {pattern}")));
+    }
+    Ok(images)
+}
+
+/// Parses `file_name,timestamp_ms` lines. Empty lines, comments and a header are skipped.
+/// A file name listed twice is an error, the images being matched by file name only.
+fn read_timestamps(csv_path: &str) -> Result<HashMap<String, u128>, RustedPipeError> {
+    let content = fs::read_to_string(csv_path)
+        .map_err(|e| read_error(format!("Cannot read timestamps {csv_path}: {e}")))?;
+
+    let mut timestamps = HashMap::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (file_name, timestamp_ms) = line
+            .split_once(',')
+            .ok_or_else(|| read_error(format!("{csv_path}:{}: missing ','", line_number + 1)))?;
+        match timestamp_ms.trim().parse::<f64>() {
+            Ok(timestamp_ms) => {
+                let file_name = file_name.trim();
+                if timestamps
+                    .insert(file_name.to_string(), (timestamp_ms * 1e6) as u128)
+                    .is_some()
+                {
+                    return Err(read_error(format!(
+                        "{csv_path}:{}: duplicate timestamp for {file_name}",
+                        line_number + 1
+                    )));
+                }
+            }
+            Err(_) if line_number == 0 => continue,
+            Err(e) => {
+                return Err(read_error(format!(
+                    "{csv_path}:{}: invalid timestamp: {e}",
+                    line_number + 1
+                )))
+            }
+        }
+    }
+    Ok(timestamps)
+}
+
+impl ImageSequenceReader {
+    /// `pattern` is either a directory or a glob pattern such as `data/plates/*.png`.
+    pub fn new(pattern: &str, fps: usize) -> Result<Self, RustedPipeError> {
+        if fps == 0 {
+            return Err(read_error(format!("Invalid fps 0 for {pattern}")));
+        }
+        Ok(Self {
+            node_id: "image_input".to_string(),
+            images: list_images(pattern)?,
+            next: 0,
+            timestamps: None,
+            fps_control: Instant::now(),
+            fps_wait: Duration::from_millis((1000 / fps) as u64),
+            fps: fps as u64,
+            end_of_stream: None,
+            output_size: Size::new(1280, 720),
+        })
+    }
+
+    /// Versions each image with the timestamp found for its file name in a `file_name,timestamp_ms` CSV file.
+    /// The images must then have distinct file names, even when they are in different folders.
+    pub fn with_timestamps(mut self, csv_path: &str) -> Result<Self, RustedPipeError> {
+        let timestamps = read_timestamps(csv_path)?;
+        let mut seen = HashSet::new();
+        for image in &self.images {
+            if !seen.insert(file_name(image)) {
+                return Err(read_error(format!(
+                    "{} has the same file name as another image, its timestamp is ambiguous",
+                    image.display()
+                )));
+            }
+            if !timestamps.contains_key(&file_name(image)) {
+                return Err(read_error(format!(
+                    "No timestamp for {} in {csv_path}",
+                    image.display()
+                )));
+            }
+        }
+        self.timestamps = Some(timestamps);
+        Ok(self)
+    }
+
+    /// Size of the frames sent downstream, 1280x720 by default.
+    /// Frames are scaled keeping their aspect ratio and padded with black borders.
+    pub fn with_output_size(mut self, output_size: Size) -> Self {
+        self.output_size = output_size;
+        self
+    }
+
+    /// Sends a message on `sender` once all the images have been sent.
+    pub fn notify_end_of_stream(mut self, sender: Sender<()>) -> Self {
+        self.end_of_stream = Some(sender);
+        self
+    }
+
+    /// Id of the graph node, labelling the frames counted by the reader.
+    pub fn with_node_id(mut self, node_id: &str) -> Self {
+        self.node_id = node_id.to_string();
+        self
+    }
+
+    fn version(&self, index: usize, image: &Path) -> DataVersion {
+        let timestamp_ns = match &self.timestamps {
+            Some(timestamps) => timestamps[&file_name(image)],
+            None => index as u128 * 1_000_000_000 / self.fps as u128,
+        };
+        DataVersion { timestamp_ns }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+impl SourceProcessor for ImageSequenceReader {
+    type OUTPUT = WriteChannel1<Mat>;
+    fn handle(&mut self, mut output: ProcessorWriter<Self::OUTPUT>) -> Result<(), RustedPipeError> {
+        if self.next >= self.images.len() {
+            println!("Done sending images!");
+            if let Some(end_of_stream) = self.end_of_stream.take() {
+                end_of_stream.send(()).ok();
+            }
+            return Err(RustedPipeError::EndOfStream());
+        }
+
+        let index = self.next;
+        let path = &self.images[index];
+        self.next += 1;
+
+        let image = imread(&path.to_string_lossy(), IMREAD_COLOR)
+            .map_err(|e| read_error(format!("Cannot read {}: {e}", path.display())))?;
+        if image.empty() {
+            println!("Skipping unreadable image {}", path.display());
+            return Ok(());
+        }
+
+        let frame_ts = self.version(index, path);
+        println!("Image {} {}", path.display(), frame_ts.timestamp_ns);
+        let (resized, _) = Letterbox::apply(&image, self.output_size, Scalar::default());
+        output.writer.c1().write(resized, &frame_ts).unwrap();
+        count_frame(&self.node_id);
+        let elapsed = self.fps_control.elapsed();
+
+        if self.fps_wait > elapsed {
+            thread::sleep(self.fps_wait - elapsed);
+        }
+
+        self.fps_control = Instant::now();
+        Ok(())
+    }
+}
+
+unsafe impl Send for ImageSequenceReader {}
+unsafe impl Sync for ImageSequenceReader {}
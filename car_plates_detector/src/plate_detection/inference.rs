@@ -0,0 +1,299 @@
+use opencv::core::get_cuda_enabled_device_count;
+use opencv::core::have_opencl;
+use opencv::core::Scalar;
+use opencv::core::Vector;
+use opencv::core::CV_32F;
+use opencv::dnn;
+use opencv::dnn::get_available_targets;
+use opencv::dnn::read_net_from_darknet;
+use opencv::dnn::Backend;
+use opencv::dnn::Net;
+use opencv::dnn::Target;
+use opencv::dnn::DNN_BACKEND_CUDA;
+use opencv::dnn::DNN_BACKEND_OPENCV;
+use opencv::dnn::DNN_TARGET_CPU;
+use opencv::dnn::DNN_TARGET_CUDA;
+use opencv::dnn::DNN_TARGET_OPENCL;
+use opencv::prelude::Mat;
+use opencv::prelude::MatTraitConstManual;
+use opencv::prelude::MatTraitManual;
+use opencv::prelude::NetTrait;
+use opencv::prelude::NetTraitConst;
+use rusted_pipe::RustedPipeError;
+use tract_onnx::prelude::*;
+
+/// Runs a network on a `[1, 3, H, W]` blob, as produced by `blob_from_image`.
+/// Every backend returns the raw output tensors so that the same `YoloProcessor` decodes them.
+pub trait InferenceBackend {
+    /// Short name used to label the metrics.
+    fn name(&self) -> &'static str;
+
+    /// Device the network effectively runs on.
+    fn device(&self) -> Device;
+
+    fn forward(&mut self, blob: &mut Mat) -> Result<Vector<Mat>, RustedPipeError>;
+}
+
+/// Inference engine used to run an ONNX model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InferenceEngine {
+    OpenCv,
+    Tract,
+}
+
+impl InferenceEngine {
+    pub fn load_onnx(
+        &self,
+        model: &str,
+        input_size: i32,
+        policy: DevicePolicy,
+    ) -> Result<Box<dyn InferenceBackend>, RustedPipeError> {
+        match self {
+            InferenceEngine::OpenCv => Ok(Box::new(OpenCvBackend::from_onnx(
+                model, input_size, policy,
+            )?)),
+            InferenceEngine::Tract => {
+                if policy != DevicePolicy::Auto && policy != DevicePolicy::Cpu {
+                    println!("Tract runs on CPU only, ignoring {:?} for {model}", policy);
+                }
+                Ok(Box::new(TractBackend::from_onnx(model, input_size)?))
+            }
+        }
+    }
+}
+
+/// Device a network runs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Device {
+    Cpu,
+    Cuda,
+    OpenCl,
+}
+
+impl Device {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Device::Cpu => "cpu",
+            Device::Cuda => "cuda",
+            Device::OpenCl => "opencl",
+        }
+    }
+
+    /// Whether OpenCV was built with support for the device and can find one.
+    fn is_available(&self) -> bool {
+        let has_target = |backend: Backend, target: Target| {
+            get_available_targets(backend)
+                .map(|targets| targets.iter().any(|t| t == target))
+                .unwrap_or(false)
+        };
+        match self {
+            Device::Cpu => true,
+            Device::Cuda => {
+                get_cuda_enabled_device_count().unwrap_or(0) > 0
+                    && has_target(Backend::DNN_BACKEND_CUDA, Target::DNN_TARGET_CUDA)
+            }
+            Device::OpenCl => {
+                have_opencl().unwrap_or(false)
+                    && has_target(Backend::DNN_BACKEND_OPENCV, Target::DNN_TARGET_OPENCL)
+            }
+        }
+    }
+}
+
+/// Device requested for a network. Unless `Cpu` is requested, the first device that is available
+/// and runs a warm-up pass is used, falling back to the CPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DevicePolicy {
+    Auto,
+    Cpu,
+    Cuda,
+    OpenCl,
+}
+
+impl DevicePolicy {
+    /// Devices to try, in order of preference.
+    fn candidates(&self) -> &'static [Device] {
+        match self {
+            DevicePolicy::Auto => &[Device::Cuda, Device::OpenCl, Device::Cpu],
+            DevicePolicy::Cpu => &[Device::Cpu],
+            DevicePolicy::Cuda => &[Device::Cuda, Device::Cpu],
+            DevicePolicy::OpenCl => &[Device::OpenCl, Device::Cpu],
+        }
+    }
+}
+
+pub(crate) fn processor_error<E: std::fmt::Display>(error: E) -> RustedPipeError {
+    RustedPipeError::ProcessorError(error.to_string())
+}
+
+/// OpenCV DNN module, on CUDA, OpenCL or CPU.
+pub struct OpenCvBackend {
+    net: Net,
+    device: Device,
+}
+
+impl OpenCvBackend {
+    pub fn from_darknet(
+        config: &str,
+        weights: &str,
+        input_size: i32,
+        policy: DevicePolicy,
+    ) -> Result<Self, RustedPipeError> {
+        let net = read_net_from_darknet(config, weights).map_err(processor_error)?;
+        Self::with_net(net, &[1, 3, input_size, input_size], policy)
+    }
+
+    pub fn from_onnx(
+        model: &str,
+        input_size: i32,
+        policy: DevicePolicy,
+    ) -> Result<Self, RustedPipeError> {
+        Self::from_onnx_with_shape(model, &[1, 3, input_size, input_size], policy)
+    }
+
+    /// Loads a model whose input is not a square colour image, e.g. a `[1, 1, 32, 100]` text line.
+    pub fn from_onnx_with_shape(
+        model: &str,
+        input_shape: &[i32],
+        policy: DevicePolicy,
+    ) -> Result<Self, RustedPipeError> {
+        let net = dnn::read_net_from_onnx(model).map_err(processor_error)?;
+        Self::with_net(net, input_shape, policy)
+    }
+
+    /// Selects the device following `policy`. A device is only kept once a forward pass on an
+    /// empty input succeeded, which also moves the first inference cost out of the stream.
+    fn with_net(
+        net: Net,
+        input_shape: &[i32],
+        policy: DevicePolicy,
+    ) -> Result<Self, RustedPipeError> {
+        let mut backend = Self {
+            net,
+            device: Device::Cpu,
+        };
+        let mut last_error = None;
+        for device in policy.candidates() {
+            if !device.is_available() {
+                println!("DNN device {} is not available", device.name());
+                continue;
+            }
+            match backend.warm_up(*device, input_shape) {
+                Ok(()) => {
+                    println!("DNN running on {} (requested {:?})", device.name(), policy);
+                    return Ok(backend);
+                }
+                Err(e) => {
+                    println!("DNN device {} failed the warm-up: {:?}", device.name(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            RustedPipeError::ProcessorError(format!("No DNN device available for {:?}", policy))
+        }))
+    }
+
+    fn warm_up(&mut self, device: Device, input_shape: &[i32]) -> Result<(), RustedPipeError> {
+        let (backend, target) = match device {
+            Device::Cpu => (DNN_BACKEND_OPENCV, DNN_TARGET_CPU),
+            Device::Cuda => (DNN_BACKEND_CUDA, DNN_TARGET_CUDA),
+            Device::OpenCl => (DNN_BACKEND_OPENCV, DNN_TARGET_OPENCL),
+        };
+        self.net
+            .set_preferable_backend(backend)
+            .map_err(processor_error)?;
+        self.net
+            .set_preferable_target(target)
+            .map_err(processor_error)?;
+        self.device = device;
+
+        let mut blob = Mat::new_nd_with_default(input_shape, CV_32F, Scalar::default())
+            .map_err(processor_error)?;
+        self.forward(&mut blob)?;
+        Ok(())
+    }
+}
+
+impl InferenceBackend for OpenCvBackend {
+    fn name(&self) -> &'static str {
+        "opencv"
+    }
+
+    fn device(&self) -> Device {
+        self.device
+    }
+
+    fn forward(&mut self, blob: &mut Mat) -> Result<Vector<Mat>, RustedPipeError> {
+        self.net
+            .set_input(blob, "", 1.0, Scalar::default())
+            .map_err(processor_error)?;
+
+        let output_names = self
+            .net
+            .get_unconnected_out_layers_names()
+            .map_err(processor_error)?;
+
+        let mut output_values = Vector::<Mat>::default();
+        self.net
+            .forward(&mut output_values, &output_names)
+            .map_err(processor_error)?;
+        Ok(output_values)
+    }
+}
+
+/// Pure Rust ONNX runtime, CPU only.
+pub struct TractBackend {
+    model: TypedRunnableModel<TypedModel>,
+    input_size: usize,
+}
+
+impl TractBackend {
+    pub fn from_onnx(model: &str, input_size: i32) -> Result<Self, RustedPipeError> {
+        let input_size = input_size as usize;
+        let input_fact =
+            InferenceFact::dt_shape(f32::datum_type(), tvec!(1, 3, input_size, input_size));
+        let model = tract_onnx::onnx()
+            .model_for_path(model)
+            .and_then(|model| model.with_input_fact(0, input_fact))
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(processor_error)?;
+        Ok(Self { model, input_size })
+    }
+}
+
+impl InferenceBackend for TractBackend {
+    fn name(&self) -> &'static str {
+        "tract"
+    }
+
+    fn device(&self) -> Device {
+        Device::Cpu
+    }
+
+    fn forward(&mut self, blob: &mut Mat) -> Result<Vector<Mat>, RustedPipeError> {
+        let data = blob.data_typed::<f32>().map_err(processor_error)?;
+        let input = Tensor::from_shape(&[1, 3, self.input_size, self.input_size], data)
+            .map_err(processor_error)?;
+
+        let outputs = self
+            .model
+            .run(tvec!(input.into()))
+            .map_err(processor_error)?;
+
+        // Copy every output into a Mat with the same dimensions, as OpenCV would return it.
+        let mut output_values = Vector::<Mat>::default();
+        for output in outputs {
+            let sizes: Vec<i32> = output.shape().iter().map(|d| *d as i32).collect();
+            let values = output.as_slice::<f32>().map_err(processor_error)?;
+            let mut mat = Mat::new_nd_with_default(&sizes, CV_32F, Scalar::default())
+                .map_err(processor_error)?;
+            mat.data_typed_mut::<f32>()
+                .map_err(processor_error)?
+                .copy_from_slice(values);
+            output_values.push(mat);
+        }
+        Ok(output_values)
+    }
+}
@@ -0,0 +1,88 @@
+use opencv::core::copy_make_border;
+use opencv::core::Rect;
+use opencv::core::Scalar;
+use opencv::core::Size;
+use opencv::core::BORDER_CONSTANT;
+use opencv::imgproc::resize;
+use opencv::imgproc::INTER_LINEAR;
+use opencv::prelude::Mat;
+use opencv::prelude::MatTraitConst;
+
+/// Padding used by the YOLO models during training.
+pub const YOLO_PAD_COLOR: (f64, f64, f64) = (114.0, 114.0, 114.0);
+
+/// Geometry of a letterboxed image: the original image was scaled by `scale`
+/// and centered in `size` with `pad_x` and `pad_y` pixels of padding.
+#[derive(Clone, Copy, Debug)]
+pub struct Letterbox {
+    pub original: Size,
+    pub size: Size,
+    pub scale: f32,
+    pub pad_x: i32,
+    pub pad_y: i32,
+}
+
+impl Letterbox {
+    pub fn new(original: Size, size: Size) -> Self {
+        let scale = f32::min(
+            size.width as f32 / original.width as f32,
+            size.height as f32 / original.height as f32,
+        );
+        let scaled_width = (original.width as f32 * scale).round() as i32;
+        let scaled_height = (original.height as f32 * scale).round() as i32;
+        Self {
+            original,
+            size,
+            scale,
+            pad_x: (size.width - scaled_width) / 2,
+            pad_y: (size.height - scaled_height) / 2,
+        }
+    }
+
+    fn scaled_size(&self) -> Size {
+        Size::new(
+            (self.original.width as f32 * self.scale).round() as i32,
+            (self.original.height as f32 * self.scale).round() as i32,
+        )
+    }
+
+    /// Maps a box from letterboxed coordinates back into the original image, clipped to its borders.
+    pub fn to_original(&self, rect: Rect) -> Rect {
+        let left = ((rect.x - self.pad_x) as f32 / self.scale).round() as i32;
+        let top = ((rect.y - self.pad_y) as f32 / self.scale).round() as i32;
+        let right = ((rect.x + rect.width - self.pad_x) as f32 / self.scale).round() as i32;
+        let bottom = ((rect.y + rect.height - self.pad_y) as f32 / self.scale).round() as i32;
+
+        let left = left.clamp(0, self.original.width);
+        let top = top.clamp(0, self.original.height);
+        let right = right.clamp(0, self.original.width);
+        let bottom = bottom.clamp(0, self.original.height);
+        Rect::new(left, top, right - left, bottom - top)
+    }
+
+    /// Resizes `image` into `size` keeping its aspect ratio, padding the borders with `color`.
+    pub fn apply(image: &Mat, size: Size, color: Scalar) -> (Mat, Letterbox) {
+        let letterbox = Letterbox::new(image.size().unwrap(), size);
+        if letterbox.original == size {
+            return (image.clone(), letterbox);
+        }
+
+        let scaled = letterbox.scaled_size();
+        let mut resized = Mat::default();
+        resize(image, &mut resized, scaled, 0.0, 0.0, INTER_LINEAR).unwrap();
+
+        let mut padded = Mat::default();
+        copy_make_border(
+            &resized,
+            &mut padded,
+            letterbox.pad_y,
+            size.height - scaled.height - letterbox.pad_y,
+            letterbox.pad_x,
+            size.width - scaled.width - letterbox.pad_x,
+            BORDER_CONSTANT,
+            color,
+        )
+        .unwrap();
+        (padded, letterbox)
+    }
+}
@@ -1,19 +1,117 @@
 pub mod bounding_box_render;
+pub mod cascade_detector;
+pub mod coco;
 pub mod dnn_ocr;
+pub mod image_reader;
+pub mod inference;
+pub mod letterbox;
 pub mod object_detector;
+pub mod ocr_backend;
+pub mod plate_association;
+pub mod plate_format;
+pub mod plate_preprocessing;
+pub mod plate_rectifier;
+pub mod plate_voting;
+pub mod regions;
 pub mod rtp_sink;
+pub mod tracker;
 pub mod video_reader;
 
+use lazy_static::lazy_static;
 use opencv::core::Rect;
+use prometheus::core::Collector;
+use prometheus::register_int_counter_vec;
+use prometheus::IntCounterVec;
 
-#[derive(Clone)]
+lazy_static! {
+    static ref FRAMES_PROCESSED: IntCounterVec = register_int_counter_vec!(
+        "frames_processed",
+        "Number of frames handled by each processor",
+        &["node"]
+    )
+    .expect("Cannot create frames counter");
+}
+
+/// Counts one more frame handled by `node`.
+pub fn count_frame(node: &str) {
+    FRAMES_PROCESSED.with_label_values(&[node]).inc();
+}
+
+/// Number of frames handled so far by each processor.
+pub fn frames_summary() -> Vec<(String, u64)> {
+    let mut summary = vec![];
+    for family in FRAMES_PROCESSED.collect() {
+        for metric in family.get_metric() {
+            let node = metric
+                .get_label()
+                .first()
+                .map(|label| label.get_value().to_string())
+                .unwrap_or_default();
+            summary.push((node, metric.get_counter().get_value() as u64));
+        }
+    }
+    summary.sort();
+    summary
+}
+
+/// An object found by a detector, in the coordinates of the frame it was found in.
+#[derive(Clone, Debug)]
+pub struct Detection {
+    pub rect: Rect,
+    pub class_id: i32,
+    pub label: String,
+    pub confidence: f32,
+    /// Box of the object this detection was searched in, e.g. the car of a plate found by
+    /// the cascade detector.
+    pub parent: Option<Rect>,
+}
+
+impl Detection {
+    pub fn new(rect: Rect, class_id: i32, confidence: f32) -> Self {
+        Self {
+            rect,
+            class_id,
+            label: String::new(),
+            confidence,
+            parent: None,
+        }
+    }
+}
+
+/// A plate, the text read on it and the car it belongs to.
+/// Confidences are the ones of the `car` and `plate` detections, and of the OCR for the text.
+#[derive(Clone, Debug)]
 pub struct CarWithText {
-    plate: Option<String>,
-    car: Rect,
+    /// Car the plate belongs to, set by the `PlateAssociator`.
+    pub car: Option<Detection>,
+    /// Index of `car` in the car detections of the frame.
+    pub car_index: Option<usize>,
+    pub plate: Detection,
+    pub text: Option<String>,
+    /// Mean confidence of the text, between 0 and 1.
+    pub text_confidence: f32,
+    /// Confidence of each character of the text, between 0 and 1. Empty when unknown.
+    pub char_confidences: Vec<f32>,
+    /// Name of the plate format matched by the text.
+    pub format: Option<String>,
+    /// The text matches none of the plate formats of the OCR.
+    pub rejected: bool,
+    /// Track of the car, set by the `PlateVoting` when the text is the consensus of the track.
+    pub track_id: Option<u64>,
 }
 
 impl CarWithText {
-    fn new(plate: Option<String>, car: Rect) -> Self {
-        return Self { plate, car };
+    fn new(text: Option<String>, text_confidence: f32, plate: Detection) -> Self {
+        return Self {
+            car: None,
+            car_index: None,
+            plate,
+            text,
+            text_confidence,
+            char_confidences: vec![],
+            format: None,
+            rejected: false,
+            track_id: None,
+        };
     }
 }
@@ -1,3 +1,6 @@
+use std::time::Instant;
+
+use lazy_static::lazy_static;
 use opencv::core::min_max_loc;
 use opencv::core::Point;
 use opencv::core::Range;
@@ -8,24 +11,18 @@ use opencv::core::ToInputOutputArray;
 use opencv::core::Vector;
 
 use opencv::core::CV_32F;
-use opencv::dnn;
 use opencv::dnn::blob_from_image;
 use opencv::dnn::nms_boxes;
 
-use opencv::dnn::read_net_from_darknet;
-use opencv::dnn::Net;
-use opencv::dnn::DNN_BACKEND_CUDA;
-use opencv::dnn::DNN_BACKEND_OPENCV;
-
-use opencv::dnn::DNN_TARGET_CPU;
-use opencv::dnn::DNN_TARGET_CUDA;
-
 use opencv::prelude::Mat;
 
 use opencv::prelude::MatTraitConst;
 
-use opencv::prelude::NetTrait;
-use opencv::prelude::NetTraitConst;
+use prometheus::exponential_buckets;
+use prometheus::register_histogram_vec;
+use prometheus::register_int_gauge_vec;
+use prometheus::HistogramVec;
+use prometheus::IntGaugeVec;
 use rusted_pipe::channels::read_channel::InputGenerator;
 use rusted_pipe::channels::typed_read_channel::ReadChannel1;
 use rusted_pipe::channels::typed_write_channel::WriteChannel1;
@@ -33,22 +30,172 @@ use rusted_pipe::graph::processor::Processor;
 use rusted_pipe::graph::processor::ProcessorWriter;
 use rusted_pipe::RustedPipeError;
 
+use super::coco::COCO_LABELS;
+use super::count_frame;
+use super::inference::DevicePolicy;
+use super::inference::InferenceBackend;
+use super::inference::InferenceEngine;
+use super::inference::OpenCvBackend;
+use super::letterbox::Letterbox;
+use super::letterbox::YOLO_PAD_COLOR;
+use super::regions::RegionsOfInterest;
+use super::Detection;
+
+lazy_static! {
+    static ref INFERENCE_TIME: HistogramVec = register_histogram_vec!(
+        "inference_time_ms",
+        "Time spent running the detection network",
+        &["node", "backend"],
+        // 1 ms to 2 s.
+        exponential_buckets(1.0, 2.0, 12).unwrap()
+    )
+    .expect("Cannot create inference timer");
+    static ref INFERENCE_DEVICE: IntGaugeVec = register_int_gauge_vec!(
+        "inference_device",
+        "Set to 1 for the backend and device each detector effectively runs on",
+        &["node", "backend", "device"]
+    )
+    .expect("Cannot create inference device gauge");
+}
+
 pub struct ObjectDetector {
-    classifier: Net,
+    node_id: String,
+    backend: Box<dyn InferenceBackend>,
     input_size: i32,
     post_processor: &'static dyn YoloProcessor,
+    labels: &'static [&'static str],
+    config: DetectionConfig,
+    tiling: Option<Tiling>,
+    regions: RegionsOfInterest,
+}
+
+/// Splits the frames in overlapping square tiles, each one sent to the network on its own so that
+/// small objects are not lost when a large frame is scaled down to the network input.
+#[derive(Clone, Copy, Debug)]
+pub struct Tiling {
+    /// Side of a tile, in frame pixels.
+    pub tile_size: i32,
+    /// Pixels shared by two neighbouring tiles, must be smaller than `tile_size`.
+    pub overlap: i32,
+}
+
+impl Tiling {
+    /// Tiles covering `frame`. The last row and column of tiles are aligned to the frame border.
+    fn tiles(&self, frame: Size) -> Vec<Rect> {
+        let width = self.tile_size.min(frame.width);
+        let height = self.tile_size.min(frame.height);
+        let stride = (self.tile_size - self.overlap).max(1);
+
+        let mut tiles = vec![];
+        for y in tile_offsets(frame.height, height, stride) {
+            for x in tile_offsets(frame.width, width, stride) {
+                tiles.push(Rect::new(x, y, width, height));
+            }
+        }
+        tiles
+    }
+}
+
+fn tile_offsets(length: i32, tile: i32, stride: i32) -> Vec<i32> {
+    let mut offsets: Vec<i32> = (0..length - tile).step_by(stride as usize).collect();
+    offsets.push(length - tile);
+    offsets
+}
+
+/// Thresholds and filters applied when decoding the detections of a YOLO network.
+#[derive(Clone, Debug)]
+pub struct DetectionConfig {
+    /// Minimum objectness of a detection.
+    pub confidence: f32,
+    /// Minimum score of the best class of a detection.
+    pub score: f32,
+    /// Detections scoring below this are dropped by the NMS.
+    pub nms_score: f32,
+    /// Overlapping detections above this IoU are suppressed by the NMS.
+    pub nms_iou: f32,
+    /// Class IDs to keep, all classes when empty.
+    pub classes: Vec<i32>,
+    /// Maximum number of detections kept by the NMS, unlimited when 0.
+    pub top_k: i32,
+}
+
+impl DetectionConfig {
+    /// Cars only (COCO class 2) for the YOLOv3 model.
+    pub fn cars() -> Self {
+        Self {
+            confidence: 0.0,
+            score: 0.5,
+            nms_score: 0.5,
+            nms_iou: 0.4,
+            classes: vec![2],
+            top_k: 0,
+        }
+    }
+
+    /// Defaults for the YOLOv5 plate model.
+    pub fn plates() -> Self {
+        Self {
+            confidence: 0.4,
+            score: 0.25,
+            nms_score: 0.5,
+            nms_iou: 0.4,
+            classes: vec![],
+            top_k: 0,
+        }
+    }
+
+    fn accepts_class(&self, class_id: i32) -> bool {
+        self.classes.is_empty() || self.classes.contains(&class_id)
+    }
 }
 
 trait YoloProcessor {
-    fn post_process(&self, img_rows: i32, img_cols: i32, outputs: &Vector<Mat>) -> Vector<Rect>;
+    /// Decodes the network outputs into boxes in the coordinates of the image before `letterbox`.
+    fn post_process(
+        &self,
+        config: &DetectionConfig,
+        letterbox: &Letterbox,
+        outputs: &Vector<Mat>,
+    ) -> Vec<Detection>;
+}
+
+/// Non maximum suppression shared by all the YOLO processors.
+fn nms(config: &DetectionConfig, candidates: Vec<Detection>) -> Vec<Detection> {
+    let boxes: Vector<Rect> = candidates.iter().map(|d| d.rect).collect();
+    let confidences: Vector<f32> = candidates.iter().map(|d| d.confidence).collect();
+
+    let mut indices = Vector::<i32>::default();
+    nms_boxes(
+        &boxes,
+        &confidences,
+        config.nms_score,
+        config.nms_iou,
+        &mut indices,
+        1.0,
+        config.top_k,
+    )
+    .unwrap();
+
+    indices
+        .iter()
+        .map(|i| candidates[i as usize].clone())
+        .collect()
 }
 
 struct YoloProcessorV3 {}
 
 impl YoloProcessor for YoloProcessorV3 {
-    fn post_process(&self, img_rows: i32, img_cols: i32, outputs: &Vector<Mat>) -> Vector<Rect> {
-        let mut confidences = Vector::<f32>::default();
-        let mut boxes = Vector::<Rect>::default();
+    fn post_process(
+        &self,
+        config: &DetectionConfig,
+        letterbox: &Letterbox,
+        outputs: &Vector<Mat>,
+    ) -> Vec<Detection> {
+        // Darknet boxes are relative to the network input.
+        let input_cols = letterbox.size.width as f32;
+        let input_rows = letterbox.size.height as f32;
+
+        let mut candidates = vec![];
 
         println!("{:?}", outputs);
         for data in outputs {
@@ -76,144 +223,345 @@ impl YoloProcessor for YoloProcessorV3 {
                 )
                 .unwrap();
                 let max_conf = min_val_p as f32;
-                if max_conf > 0.5 && max_loc_p.x == 2 {
+                let objectness = *data.at_2d::<f32>(j, 4).unwrap();
+                if objectness >= config.confidence
+                    && max_conf > config.score
+                    && config.accepts_class(max_loc_p.x)
+                {
                     let center_x: i32 =
-                        (data.at_2d::<f32>(j, 0).unwrap() * img_cols as f32).round() as i32;
+                        (data.at_2d::<f32>(j, 0).unwrap() * input_cols).round() as i32;
                     let center_y: i32 =
-                        (data.at_2d::<f32>(j, 1).unwrap() * img_rows as f32).round() as i32;
-                    let width: i32 =
-                        (data.at_2d::<f32>(j, 2).unwrap() * img_cols as f32).round() as i32;
+                        (data.at_2d::<f32>(j, 1).unwrap() * input_rows).round() as i32;
+                    let width: i32 = (data.at_2d::<f32>(j, 2).unwrap() * input_cols).round() as i32;
                     let height: i32 =
-                        (data.at_2d::<f32>(j, 3).unwrap() * img_rows as f32).round() as i32;
+                        (data.at_2d::<f32>(j, 3).unwrap() * input_rows).round() as i32;
                     let left = center_x - width / 2;
                     let top = center_y - height / 2;
 
-                    confidences.push(max_conf);
-                    boxes.push(Rect::new(left, top, width, height));
+                    candidates.push(Detection::new(
+                        letterbox.to_original(Rect::new(left, top, width, height)),
+                        max_loc_p.x,
+                        max_conf,
+                    ));
                 }
             }
         }
 
-        let mut indices = Vector::<i32>::default();
-        nms_boxes(&boxes, &confidences, 0.5, 0.4, &mut indices, 1.0, 0).unwrap();
-
-        let mut output = Vector::<Rect>::default();
-        for i in indices {
-            output.push(boxes.get(i as usize).unwrap());
-        }
-
-        return output;
+        return nms(config, candidates);
     }
 }
 
-struct YoloProcessorV5 {
-    input_size: i32,
-}
+/// Decodes YOLOv5 outputs, laid out as `[1, N, 5 + classes]`: one row per candidate with the box,
+/// the objectness and the class scores. N depends on the input size and the anchors of the model.
+struct YoloProcessorV5 {}
 
 impl YoloProcessor for YoloProcessorV5 {
-    fn post_process(&self, img_rows: i32, img_cols: i32, outputs: &Vector<Mat>) -> Vector<Rect> {
-        let mut confidences = Vector::<f32>::default();
-        let mut boxes = Vector::<Rect>::default();
-
-        let x_factor = img_cols as f32 / self.input_size as f32;
-        let y_factor = img_rows as f32 / self.input_size as f32;
+    fn post_process(
+        &self,
+        config: &DetectionConfig,
+        letterbox: &Letterbox,
+        outputs: &Vector<Mat>,
+    ) -> Vec<Detection> {
+        let mut candidates = vec![];
 
         for data in outputs {
-            for j in 0..25200 {
-                let confidence: f32 = *data.at_3d(0, j, 4).unwrap();
-                if confidence > 0.4 {
-                    let class_score: f32 = *data.at_3d(0, j, 5).unwrap();
-                    if class_score > 0.25 {
-                        let cx: f32 = *data.at_3d(0, j, 0).unwrap();
-                        let cy: f32 = *data.at_3d(0, j, 1).unwrap();
-                        let w: f32 = *data.at_3d(0, j, 2).unwrap();
-                        let h: f32 = *data.at_3d(0, j, 3).unwrap();
-                        let left = (cx - 0.5 * w) * x_factor;
-                        let top = (cy - 0.5 * h) * y_factor;
-                        let width = w * x_factor;
-                        let height = h * y_factor;
-
-                        confidences.push(confidence);
-                        boxes.push(Rect::new(
-                            left as i32,
-                            top as i32,
-                            width as i32,
-                            height as i32,
+            let output = match OutputView::new(&data) {
+                Some(output) => output,
+                None => {
+                    println!("Unexpected YOLOv5 output of shape {:?}", data.mat_size());
+                    continue;
+                }
+            };
+            if output.cols < 6 {
+                println!("Unexpected YOLOv5 output with {} columns", output.cols);
+                continue;
+            }
+
+            for j in 0..output.rows {
+                let confidence = output.at(j, 4);
+                if confidence > config.confidence {
+                    let (class_id, class_score) = output.best_in_row(j, 5);
+                    if class_score > config.score && config.accepts_class(class_id) {
+                        let cx = output.at(j, 0);
+                        let cy = output.at(j, 1);
+                        let w = output.at(j, 2);
+                        let h = output.at(j, 3);
+                        // Boxes are in pixels of the letterboxed network input.
+                        let left = cx - 0.5 * w;
+                        let top = cy - 0.5 * h;
+
+                        let rect = Rect::new(left as i32, top as i32, w as i32, h as i32);
+                        candidates.push(Detection::new(
+                            letterbox.to_original(rect),
+                            class_id,
+                            confidence,
                         ));
                     }
                 }
             }
         }
 
-        let mut indices = Vector::<i32>::default();
-        nms_boxes(&boxes, &confidences, 0.5, 0.4, &mut indices, 1.0, 0).unwrap();
+        return nms(config, candidates);
+    }
+}
+
+/// Decodes YOLOv8 outputs, laid out as `[1, 4 + classes, N]`: one column per candidate
+/// with the box followed by the class scores and no objectness.
+struct YoloProcessorV8 {}
+
+impl YoloProcessor for YoloProcessorV8 {
+    fn post_process(
+        &self,
+        config: &DetectionConfig,
+        letterbox: &Letterbox,
+        outputs: &Vector<Mat>,
+    ) -> Vec<Detection> {
+        let mut candidates = vec![];
 
-        let mut output = Vector::<Rect>::default();
-        for i in indices {
-            output.push(boxes.get(i as usize).unwrap());
+        for data in outputs {
+            let output = match OutputView::new(&data) {
+                Some(output) => output,
+                None => {
+                    println!("Unexpected YOLOv8 output of shape {:?}", data.mat_size());
+                    continue;
+                }
+            };
+
+            for j in 0..output.cols {
+                let (class_id, class_score) = output.best_in_col(j, 4);
+                if class_score > config.score && config.accepts_class(class_id) {
+                    let cx = output.at(0, j);
+                    let cy = output.at(1, j);
+                    let w = output.at(2, j);
+                    let h = output.at(3, j);
+                    // Boxes are in pixels of the letterboxed network input.
+                    let left = cx - 0.5 * w;
+                    let top = cy - 0.5 * h;
+
+                    let rect = Rect::new(left as i32, top as i32, w as i32, h as i32);
+                    candidates.push(Detection::new(
+                        letterbox.to_original(rect),
+                        class_id,
+                        class_score,
+                    ));
+                }
+            }
         }
 
-        return output;
+        return nms(config, candidates);
     }
 }
 
-impl ObjectDetector {
-    pub fn car_detector(use_gpu: bool) -> Self {
-        let mut classifier =
-            read_net_from_darknet("models/yolov3.cfg", "models/yolov3.weights").unwrap();
+/// 2D view over a network output, ignoring the batch dimension when there is one.
+struct OutputView<'a> {
+    data: &'a Mat,
+    rows: i32,
+    cols: i32,
+}
 
-        if use_gpu {
-            classifier.set_preferable_backend(DNN_BACKEND_CUDA).unwrap();
-            classifier.set_preferable_target(DNN_TARGET_CUDA).unwrap();
+impl<'a> OutputView<'a> {
+    /// `None` unless `data` is `[rows, cols]` or `[1, rows, cols]`.
+    fn new(data: &'a Mat) -> Option<Self> {
+        let size = data.mat_size();
+        match size.len() {
+            2 => {}
+            3 if size[0] == 1 => {}
+            _ => return None,
+        }
+        let dims = size.len();
+        Some(Self {
+            data,
+            rows: size[dims - 2],
+            cols: size[dims - 1],
+        })
+    }
+
+    fn at(&self, row: i32, col: i32) -> f32 {
+        if self.data.dims() == 3 {
+            *self.data.at_3d(0, row, col).unwrap()
         } else {
-            classifier.set_preferable_backend(DNN_BACKEND_OPENCV).unwrap();
-            classifier.set_preferable_target(DNN_TARGET_CPU).unwrap();
+            *self.data.at_2d(row, col).unwrap()
         }
-        
-        return ObjectDetector {
-            classifier,
-            input_size: 416,
-            post_processor: &YoloProcessorV3 {},
-        };
     }
 
-    pub fn plate_detector(use_gpu: bool) -> Self {
-        let mut classifier = dnn::read_net_from_onnx("models/plate_best.onnx").unwrap();
+    /// Index, counted from `first`, and value of the highest score in `row` from column `first`.
+    fn best_in_row(&self, row: i32, first: i32) -> (i32, f32) {
+        let mut best = (0, f32::MIN);
+        for col in first..self.cols {
+            let score = self.at(row, col);
+            if score > best.1 {
+                best = (col - first, score);
+            }
+        }
+        best
+    }
 
-        if use_gpu {
-            classifier.set_preferable_backend(DNN_BACKEND_CUDA).unwrap();
-            classifier.set_preferable_target(DNN_TARGET_CUDA).unwrap();
-        } else {
-            classifier.set_preferable_backend(DNN_BACKEND_OPENCV).unwrap();
-            classifier.set_preferable_target(DNN_TARGET_CPU).unwrap();
+    /// Index, counted from `first`, and value of the highest score in `col` from row `first`.
+    fn best_in_col(&self, col: i32, first: i32) -> (i32, f32) {
+        let mut best = (0, f32::MIN);
+        for row in first..self.rows {
+            let score = self.at(row, col);
+            if score > best.1 {
+                best = (row - first, score);
+            }
         }
+        best
+    }
+}
+
+/// Output layout of the YOLO network loaded by an `ObjectDetector`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum YoloVersion {
+    V3,
+    V5,
+    V8,
+}
+
+impl YoloVersion {
+    fn post_processor(&self) -> &'static dyn YoloProcessor {
+        match self {
+            YoloVersion::V3 => &YoloProcessorV3 {},
+            YoloVersion::V5 => &YoloProcessorV5 {},
+            YoloVersion::V8 => &YoloProcessorV8 {},
+        }
+    }
+}
+
+impl ObjectDetector {
+    pub fn car_detector(
+        policy: DevicePolicy,
+        config: DetectionConfig,
+    ) -> Result<Self, RustedPipeError> {
+        let backend =
+            OpenCvBackend::from_darknet("models/yolov3.cfg", "models/yolov3.weights", 416, policy)?;
+
+        Ok(ObjectDetector::new(
+            "car_detector",
+            Box::new(backend),
+            416,
+            &YoloProcessorV3 {},
+            &COCO_LABELS,
+            config,
+        ))
+    }
+
+    pub fn plate_detector(
+        policy: DevicePolicy,
+        config: DetectionConfig,
+    ) -> Result<Self, RustedPipeError> {
+        Self::plate_detector_from_onnx(
+            policy,
+            "models/plate_best.onnx",
+            640,
+            YoloVersion::V5,
+            InferenceEngine::OpenCv,
+            config,
+        )
+    }
 
+    /// Plate detector running any YOLO ONNX export, `version` selects how its output is decoded
+    /// and `engine` which runtime executes the model.
+    pub fn plate_detector_from_onnx(
+        policy: DevicePolicy,
+        model: &str,
+        input_size: i32,
+        version: YoloVersion,
+        engine: InferenceEngine,
+        config: DetectionConfig,
+    ) -> Result<Self, RustedPipeError> {
+        let backend = engine.load_onnx(model, input_size, policy)?;
+
+        Ok(ObjectDetector::new(
+            "plate_detector",
+            backend,
+            input_size,
+            version.post_processor(),
+            &["plate"],
+            config,
+        ))
+    }
+
+    fn new(
+        node_id: &str,
+        backend: Box<dyn InferenceBackend>,
+        input_size: i32,
+        post_processor: &'static dyn YoloProcessor,
+        labels: &'static [&'static str],
+        config: DetectionConfig,
+    ) -> Self {
+        INFERENCE_DEVICE
+            .with_label_values(&[node_id, backend.name(), backend.device().name()])
+            .set(1);
         return ObjectDetector {
-            classifier,
-            input_size: 640,
-            post_processor: &YoloProcessorV5 { input_size: 640 },
+            node_id: node_id.to_string(),
+            backend,
+            input_size,
+            post_processor,
+            labels,
+            config,
+            tiling: None,
+            regions: RegionsOfInterest::default(),
         };
     }
-}
 
-unsafe impl Send for ObjectDetector {}
-unsafe impl Sync for ObjectDetector {}
+    /// Runs the network on each tile of the frame instead of the whole frame. Detections of all
+    /// the tiles are merged by a global NMS.
+    pub fn with_tiling(mut self, tiling: Tiling) -> Result<Self, RustedPipeError> {
+        if tiling.overlap < 0 || tiling.overlap >= tiling.tile_size {
+            return Err(RustedPipeError::ProcessorError(format!(
+                "Tile overlap {} must be between 0 and the tile size {}",
+                tiling.overlap, tiling.tile_size
+            )));
+        }
+        self.tiling = Some(tiling);
+        Ok(self)
+    }
 
-impl Processor for ObjectDetector {
-    type OUTPUT = WriteChannel1<Vector<Rect>>;
-    type INPUT = ReadChannel1<Mat>;
-    fn handle(
-        &mut self,
-        input: <Self::INPUT as InputGenerator>::INPUT,
-        mut output: ProcessorWriter<Self::OUTPUT>,
-    ) -> Result<(), RustedPipeError> {
-        let image_packet = &input.c1().unwrap();
-        println!("Object Detection {}", image_packet.version.timestamp_ns);
+    /// Id of the graph node, labelling the frames counted and the inference metrics of the
+    /// detector.
+    pub fn with_node_id(mut self, node_id: &str) -> Self {
+        let backend = self.backend.name();
+        let device = self.backend.device().name();
+        let _ = INFERENCE_DEVICE.remove_label_values(&[self.node_id.as_str(), backend, device]);
+        INFERENCE_DEVICE
+            .with_label_values(&[node_id, backend, device])
+            .set(1);
+        self.node_id = node_id.to_string();
+        self
+    }
 
-        let image = &image_packet.data;
+    pub(crate) fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Merges the detections found in overlapping parts of a frame with the NMS of this detector.
+    pub(crate) fn merge(&self, detections: Vec<Detection>) -> Vec<Detection> {
+        nms(&self.config, detections)
+    }
+
+    /// Only emits the detections centred in the active zones of `regions`.
+    pub fn with_regions(mut self, regions: RegionsOfInterest) -> Self {
+        self.regions = regions;
+        self
+    }
 
+    /// Drops the detections that are not centred in the active zones of the regions.
+    pub(crate) fn retain_in_regions(&self, detections: &mut Vec<Detection>) {
+        if !self.regions.is_empty() {
+            detections.retain(|detection| self.regions.accepts(&detection.rect));
+        }
+    }
+
+    /// Detections in `image`, in its own coordinates.
+    pub(crate) fn detect(&mut self, image: &Mat) -> Result<Vec<Detection>, RustedPipeError> {
+        // Keep the aspect ratio of the frame, the network input is padded instead of stretched.
+        let (input_image, letterbox) = Letterbox::apply(
+            image,
+            Size::new(self.input_size, self.input_size),
+            Scalar::from(YOLO_PAD_COLOR),
+        );
         let mut blob = blob_from_image(
-            &image,
+            &input_image,
             1.0 / 255.0,
             Size::new(self.input_size, self.input_size),
             Scalar::default(),
@@ -223,28 +571,158 @@ impl Processor for ObjectDetector {
         )
         .unwrap();
 
-        // Set the input of the network
-        self.classifier
-            .set_input(&mut blob, "", 1.0, Scalar::default())
-            .unwrap();
+        let inference_start = Instant::now();
+        let output_values = self.backend.forward(&mut blob)?;
+        INFERENCE_TIME
+            .with_label_values(&[self.node_id.as_str(), self.backend.name()])
+            .observe(inference_start.elapsed().as_secs_f64() * 1000.0);
+
+        let mut detections =
+            self.post_processor
+                .post_process(&self.config, &letterbox, &output_values);
+        for detection in detections.iter_mut() {
+            detection.label = class_label(self.labels, detection.class_id);
+        }
+        return Ok(detections);
+    }
+}
 
-        let output_names = self.classifier.get_unconnected_out_layers_names().unwrap();
+/// Label of `class_id`, `class_<id>` for the classes of models with more classes than labels.
+fn class_label(labels: &[&str], class_id: i32) -> String {
+    match usize::try_from(class_id).ok().and_then(|id| labels.get(id)) {
+        Some(label) => label.to_string(),
+        None => format!("class_{class_id}"),
+    }
+}
 
-        let mut output_values = Vector::<Mat>::default();
+unsafe impl Send for ObjectDetector {}
+unsafe impl Sync for ObjectDetector {}
 
-        self.classifier
-            .forward(&mut output_values, &output_names)
-            .unwrap();
-        let out = self
-            .post_processor
-            .post_process(image.rows(), image.cols(), &output_values);
+impl Processor for ObjectDetector {
+    type OUTPUT = WriteChannel1<Vec<Detection>>;
+    type INPUT = ReadChannel1<Mat>;
+    fn handle(
+        &mut self,
+        input: <Self::INPUT as InputGenerator>::INPUT,
+        mut output: ProcessorWriter<Self::OUTPUT>,
+    ) -> Result<(), RustedPipeError> {
+        let image_packet = &input.c1().unwrap();
+        println!("Object Detection {}", image_packet.version.timestamp_ns);
+
+        let image = &image_packet.data;
+
+        let mut out = match self.tiling {
+            Some(tiling) => {
+                let mut candidates = vec![];
+                for tile in tiling.tiles(image.size().unwrap()) {
+                    let tile_image = image.apply_1(tile).unwrap();
+                    for mut detection in self.detect(&tile_image)? {
+                        detection.rect.x += tile.x;
+                        detection.rect.y += tile.y;
+                        candidates.push(detection);
+                    }
+                }
+                // Objects in the overlap are found by more than one tile.
+                self.merge(candidates)
+            }
+            None => self.detect(image)?,
+        };
+        self.retain_in_regions(&mut out);
 
-        //let out = Vector::<Rect>::default();
         output
             .writer
             .c1()
             .write(out, &image_packet.version)
             .unwrap();
+        count_frame(&self.node_id);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opencv::prelude::*;
+
+    /// `[N, C]` output holding `rows`.
+    fn output_2d(rows: &[&[f32]]) -> Mat {
+        Mat::from_slice_2d(rows).unwrap()
+    }
+
+    /// `[1, N, C]` output holding `rows`.
+    fn output_3d(rows: &[&[f32]]) -> Mat {
+        let sizes = [1, rows.len() as i32, rows[0].len() as i32];
+        let mut output = Mat::new_nd_with_default(&sizes, CV_32F, Scalar::all(0.0)).unwrap();
+        for (i, row) in rows.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                *output.at_3d_mut::<f32>(0, i as i32, j as i32).unwrap() = *value;
+            }
+        }
+        output
+    }
+
+    const PLATE: [f32; 8] = [100.0, 200.0, 50.0, 20.0, 0.9, 0.1, 0.7, 0.2];
+    const BACKGROUND: [f32; 8] = [300.0, 300.0, 40.0, 40.0, 0.1, 0.8, 0.1, 0.1];
+
+    #[test]
+    fn output_view_of_2d_output() {
+        let data = output_2d(&[&PLATE, &BACKGROUND]);
+        let output = OutputView::new(&data).unwrap();
+        assert_eq!((output.rows, output.cols), (2, 8));
+        assert_eq!(output.at(1, 0), 300.0);
+    }
+
+    #[test]
+    fn output_view_of_3d_output() {
+        let data = output_3d(&[&PLATE, &BACKGROUND]);
+        let output = OutputView::new(&data).unwrap();
+        assert_eq!((output.rows, output.cols), (2, 8));
+        assert_eq!(output.at(1, 0), 300.0);
+    }
+
+    #[test]
+    fn output_view_rejects_other_shapes() {
+        let data = Mat::new_nd_with_default(&[1, 1, 2, 8], CV_32F, Scalar::all(0.0)).unwrap();
+        assert!(OutputView::new(&data).is_none());
+        let data = Mat::new_nd_with_default(&[2, 2, 8], CV_32F, Scalar::all(0.0)).unwrap();
+        assert!(OutputView::new(&data).is_none());
+    }
+
+    #[test]
+    fn best_in_row_returns_the_class_with_the_highest_score() {
+        let data = output_3d(&[&PLATE, &BACKGROUND]);
+        let output = OutputView::new(&data).unwrap();
+        assert_eq!(output.best_in_row(0, 5), (1, 0.7));
+        assert_eq!(output.best_in_row(1, 5), (0, 0.8));
+    }
+
+    fn identity() -> Letterbox {
+        Letterbox::new(Size::new(640, 640), Size::new(640, 640))
+    }
+
+    #[test]
+    fn v5_decodes_boxes_above_the_thresholds() {
+        let outputs = Vector::from_iter([output_3d(&[&PLATE, &BACKGROUND])]);
+        let detections =
+            YoloProcessorV5 {}.post_process(&DetectionConfig::plates(), &identity(), &outputs);
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].rect, Rect::new(75, 190, 50, 20));
+        assert_eq!(detections[0].class_id, 1);
+        assert_eq!(detections[0].confidence, 0.9);
+    }
+
+    #[test]
+    fn v5_skips_outputs_without_class_scores() {
+        let outputs = Vector::from_iter([output_2d(&[&PLATE[..5]])]);
+        let detections =
+            YoloProcessorV5 {}.post_process(&DetectionConfig::plates(), &identity(), &outputs);
+        assert!(detections.is_empty());
+    }
+
+    #[test]
+    fn class_label_falls_back_to_the_class_id() {
+        assert_eq!(class_label(&["plate"], 0), "plate");
+        assert_eq!(class_label(&["plate"], 1), "class_1");
+        assert_eq!(class_label(&["plate"], -1), "class_-1");
+    }
+}
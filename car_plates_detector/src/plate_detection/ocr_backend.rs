@@ -0,0 +1,263 @@
+use leptess::tesseract;
+use leptess::tesseract::TessApi;
+use opencv::core::Scalar;
+use opencv::core::Size;
+use opencv::core::CV_32F;
+use opencv::dnn::blob_from_image;
+use opencv::prelude::Mat;
+use opencv::prelude::MatTraitConst;
+use opencv::prelude::MatTraitConstManual;
+use rusted_pipe::RustedPipeError;
+use std::ffi::CString;
+
+use super::inference::processor_error;
+use super::inference::DevicePolicy;
+use super::inference::InferenceBackend;
+use super::inference::OpenCvBackend;
+
+/// Text read on a plate, without spaces.
+#[derive(Clone, Debug, Default)]
+pub struct PlateRead {
+    pub text: String,
+    /// Between 0 and 1.
+    pub confidence: f32,
+    /// Confidence of each character of `text`, between 0 and 1. Empty when unknown.
+    pub char_confidences: Vec<f32>,
+}
+
+/// Reads the text of a single line plate crop, as a contiguous grey `Mat`.
+/// A backend is moved to the thread it reads on, see the workers of `DnnOcrReader`.
+pub trait OcrBackend: Send {
+    /// Short name used in the logs.
+    fn name(&self) -> &'static str;
+
+    fn read(&mut self, plate: &Mat) -> Result<PlateRead, RustedPipeError>;
+}
+
+/// Tesseract with the `licence` model of the `models` folder, in single line mode.
+pub struct TesseractBackend {
+    api: TessApi,
+}
+
+impl Default for TesseractBackend {
+    fn default() -> Self {
+        let mut api = tesseract::TessApi::new(Some("models"), "licence").unwrap();
+        let data_path_cstr = CString::new("models").unwrap();
+        let lang = CString::new("licence").unwrap();
+
+        api.raw
+            .init_4(Some(data_path_cstr.as_ref()), Some(lang.as_ref()), 1)
+            .unwrap();
+        api.raw
+            .set_variable(
+                &CString::new("tessedit_char_whitelist").unwrap(),
+                &CString::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789").unwrap(),
+            )
+            .unwrap();
+        api.raw
+            .set_variable(
+                &CString::new("tessedit_pageseg_mode").unwrap(),
+                &CString::new("7").unwrap(),
+            )
+            .unwrap();
+        // Adds the confidence of every character to the hOCR output.
+        api.raw
+            .set_variable(
+                &CString::new("hocr_char_boxes").unwrap(),
+                &CString::new("1").unwrap(),
+            )
+            .unwrap();
+
+        Self { api }
+    }
+}
+
+impl TesseractBackend {
+    /// Confidence of each character of the last read, between 0 and 1.
+    fn char_confidences(&mut self) -> Vec<f32> {
+        match self.api.get_hocr_text(0) {
+            Ok(hocr) => parse_char_confidences(&hocr),
+            Err(e) => {
+                println!("Cannot get the OCR character confidences: {:?}", e);
+                vec![]
+            }
+        }
+    }
+}
+
+/// Reads the `x_conf` of the `ocrx_cinfo` spans Tesseract writes with `hocr_char_boxes`, e.g.
+/// `<span class='ocrx_cinfo' title='x_bboxes 1 2 3 4; x_conf 96.5'>A</span>`.
+fn parse_char_confidences(hocr: &str) -> Vec<f32> {
+    let mut confidences = vec![];
+    for span in hocr.split("class='ocrx_cinfo'").skip(1) {
+        let title = span.split('>').next().unwrap_or_default();
+        let confidence = title
+            .split(';')
+            .find_map(|property| property.trim().strip_prefix("x_conf "))
+            .and_then(|value| value.trim_end_matches('\'').trim().parse::<f32>().ok());
+        if let Some(confidence) = confidence {
+            confidences.push(confidence / 100.0);
+        }
+    }
+    confidences
+}
+
+// A backend is only used by one thread at a time.
+unsafe impl Send for TesseractBackend {}
+
+impl OcrBackend for TesseractBackend {
+    fn name(&self) -> &'static str {
+        "tesseract"
+    }
+
+    fn read(&mut self, plate: &Mat) -> Result<PlateRead, RustedPipeError> {
+        let cols = plate.cols();
+        let rows = plate.rows();
+        self.api
+            .raw
+            .set_image(
+                plate.data_bytes().map_err(processor_error)?,
+                cols,
+                rows,
+                1,
+                cols,
+            )
+            .map_err(processor_error)?;
+        let text = self.api.get_utf8_text().map_err(processor_error)?;
+        Ok(PlateRead {
+            text: text.trim().replace(' ', ""),
+            confidence: self.api.mean_text_conf() as f32 / 100.0,
+            char_confidences: self.char_confidences(),
+        })
+    }
+}
+
+/// Scores returned by a CRNN export for each time step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrnnOutput {
+    /// Raw scores or log-probabilities, turned into probabilities by a softmax.
+    Logits,
+    /// Probabilities, the model ending with a softmax.
+    Probabilities,
+}
+
+/// CRNN text recognizer exported to ONNX, run by the OpenCV DNN module and decoded by CTC.
+/// The model takes a `[1, 1, height, width]` grey line scaled to [-1, 1] and returns the scores of
+/// every class for each time step, the class 0 being the CTC blank.
+pub struct CrnnBackend {
+    network: OpenCvBackend,
+    input_size: Size,
+    /// Characters of the classes 1 and following.
+    alphabet: Vec<char>,
+    output: CrnnOutput,
+}
+
+impl CrnnBackend {
+    pub fn from_onnx(
+        model: &str,
+        alphabet: &str,
+        input_size: Size,
+        output: CrnnOutput,
+        policy: DevicePolicy,
+    ) -> Result<Self, RustedPipeError> {
+        let network = OpenCvBackend::from_onnx_with_shape(
+            model,
+            &[1, 1, input_size.height, input_size.width],
+            policy,
+        )?;
+        Ok(Self {
+            network,
+            input_size,
+            alphabet: alphabet.chars().collect(),
+            output,
+        })
+    }
+}
+
+unsafe impl Send for CrnnBackend {}
+
+impl OcrBackend for CrnnBackend {
+    fn name(&self) -> &'static str {
+        "crnn"
+    }
+
+    fn read(&mut self, plate: &Mat) -> Result<PlateRead, RustedPipeError> {
+        let mut blob = blob_from_image(
+            plate,
+            1.0 / 127.5,
+            self.input_size,
+            Scalar::all(127.5),
+            false,
+            false,
+            CV_32F,
+        )
+        .map_err(processor_error)?;
+        let outputs = self.network.forward(&mut blob)?;
+        let output = outputs.get(0).map_err(processor_error)?;
+
+        // `[T, 1, C]`, `[1, T, C]` or `[T, C]` depending on the export, with a batch of one.
+        let classes = *output.mat_size().last().unwrap_or(&0) as usize;
+        let scores = output.data_typed::<f32>().map_err(processor_error)?;
+        if classes != self.alphabet.len() + 1 {
+            return Err(RustedPipeError::ProcessorError(format!(
+                "CRNN returns {} classes for an alphabet of {} characters",
+                classes,
+                self.alphabet.len()
+            )));
+        }
+        Ok(ctc_greedy_decode(
+            scores,
+            classes,
+            &self.alphabet,
+            self.output,
+        ))
+    }
+}
+
+/// Keeps the best class of every time step, then merges the repeated classes and drops the
+/// blanks. The confidence of a character is the best probability of the steps it spans.
+fn ctc_greedy_decode(
+    scores: &[f32],
+    classes: usize,
+    alphabet: &[char],
+    output: CrnnOutput,
+) -> PlateRead {
+    let mut read = PlateRead::default();
+    let mut previous = 0;
+    for step in scores.chunks_exact(classes) {
+        let (best, score) = step
+            .iter()
+            .enumerate()
+            .fold((0, f32::MIN), |best, (class, score)| {
+                if *score > best.1 {
+                    (class, *score)
+                } else {
+                    best
+                }
+            });
+        let probability = match output {
+            // Softmax, which also turns log-probabilities back into probabilities.
+            CrnnOutput::Logits => {
+                let max = step.iter().cloned().fold(f32::MIN, f32::max);
+                let sum: f32 = step.iter().map(|score| (score - max).exp()).sum();
+                (score - max).exp() / sum
+            }
+            CrnnOutput::Probabilities => score,
+        };
+
+        if best != 0 && best == previous {
+            if let Some(last) = read.char_confidences.last_mut() {
+                *last = last.max(probability);
+            }
+        } else if best != 0 {
+            read.text.push(alphabet[best - 1].to_ascii_uppercase());
+            read.char_confidences.push(probability);
+        }
+        previous = best;
+    }
+    if !read.char_confidences.is_empty() {
+        read.confidence =
+            read.char_confidences.iter().sum::<f32>() / read.char_confidences.len() as f32;
+    }
+    read
+}
@@ -0,0 +1,112 @@
+use opencv::core::Rect;
+use rusted_pipe::channels::read_channel::InputGenerator;
+use rusted_pipe::channels::typed_read_channel::ReadChannel2;
+use rusted_pipe::channels::typed_write_channel::WriteChannel1;
+use rusted_pipe::graph::processor::Processor;
+use rusted_pipe::graph::processor::ProcessorWriter;
+use rusted_pipe::RustedPipeError;
+
+use super::count_frame;
+use super::CarWithText;
+use super::Detection;
+
+/// Matches the plates read by the OCR with the cars found in the same frame.
+/// Each car gets at most one plate; plates without a car are emitted with `car` set to `None`.
+pub struct PlateAssociator {
+    node_id: String,
+    min_coverage: f32,
+}
+
+impl PlateAssociator {
+    /// `min_coverage` is the minimum fraction of the plate box that must lie inside the car box.
+    pub fn new(min_coverage: f32) -> Self {
+        Self {
+            node_id: "plate_association".to_string(),
+            min_coverage,
+        }
+    }
+
+    /// Id of the graph node, labelling the frames counted by the association.
+    pub fn with_node_id(mut self, node_id: &str) -> Self {
+        self.node_id = node_id.to_string();
+        self
+    }
+}
+
+impl Default for PlateAssociator {
+    fn default() -> Self {
+        Self::new(0.6)
+    }
+}
+
+fn area(rect: &Rect) -> i32 {
+    rect.width.max(0) * rect.height.max(0)
+}
+
+/// Fraction of `plate` inside `car`.
+fn coverage(plate: &Rect, car: &Rect) -> f32 {
+    let plate_area = area(plate);
+    if plate_area == 0 {
+        return 0.0;
+    }
+    area(&(*plate & *car)) as f32 / plate_area as f32
+}
+
+/// Pairs each plate with the car that contains it. Plates found by the cascade detector are
+/// paired with the car they were found in, the others with the car covering most of them,
+/// the smallest car winning ties, e.g. for a car in front of a bus.
+pub fn associate(
+    cars: &[Detection],
+    mut plates: Vec<CarWithText>,
+    min_coverage: f32,
+) -> Vec<CarWithText> {
+    let mut pairs = vec![];
+    for (p, plate) in plates.iter().enumerate() {
+        for (c, car) in cars.iter().enumerate() {
+            let score = match plate.plate.parent {
+                Some(parent) if parent == car.rect => 1.0,
+                Some(_) => continue,
+                None => coverage(&plate.plate.rect, &car.rect),
+            };
+            if score >= min_coverage {
+                pairs.push((score, area(&car.rect), p, c));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let mut car_taken = vec![false; cars.len()];
+    for (_, _, p, c) in pairs {
+        if car_taken[c] || plates[p].car.is_some() {
+            continue;
+        }
+        car_taken[c] = true;
+        plates[p].car = Some(cars[c].clone());
+        plates[p].car_index = Some(c);
+    }
+    plates
+}
+
+impl Processor for PlateAssociator {
+    type INPUT = ReadChannel2<Vec<Detection>, Vec<CarWithText>>;
+    type OUTPUT = WriteChannel1<Vec<CarWithText>>;
+    fn handle(
+        &mut self,
+        mut input: <Self::INPUT as InputGenerator>::INPUT,
+        mut output: ProcessorWriter<Self::OUTPUT>,
+    ) -> Result<(), RustedPipeError> {
+        let plates_packet = input.c2_owned().unwrap();
+        println!("Plate association {}", plates_packet.version.timestamp_ns);
+
+        let cars = input.c1_owned().map(|cars| cars.data).unwrap_or_default();
+        let out = associate(&cars, plates_packet.data, self.min_coverage);
+
+        output
+            .writer
+            .c1()
+            .write(out, &plates_packet.version)
+            .unwrap();
+        count_frame(&self.node_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use regex::Regex;
+
+/// A plate format. `read` validates an OCR read and returns the plate in this format,
+/// or `None` when the read cannot be a plate of this format.
+pub trait PlateFormat {
+    fn name(&self) -> &str;
+
+    fn read(&self, text: &str) -> Option<String>;
+}
+
+/// Letter mistaken for a digit by the OCR, or the other way around.
+fn to_digit(c: char) -> Option<char> {
+    match c {
+        '0'..='9' => Some(c),
+        'O' | 'D' | 'Q' => Some('0'),
+        'I' | 'L' => Some('1'),
+        'Z' => Some('2'),
+        'A' => Some('4'),
+        'S' => Some('5'),
+        'G' => Some('6'),
+        'T' => Some('7'),
+        'B' => Some('8'),
+        _ => None,
+    }
+}
+
+fn to_letter(c: char) -> Option<char> {
+    match c {
+        'A'..='Z' => Some(c),
+        '0' => Some('O'),
+        '1' => Some('I'),
+        '2' => Some('Z'),
+        '4' => Some('A'),
+        '5' => Some('S'),
+        '6' => Some('G'),
+        '7' => Some('T'),
+        '8' => Some('B'),
+        _ => None,
+    }
+}
+
+/// Format given as one character per position: `A` for a letter, `0` for a digit and `?` for
+/// either. Confusable characters are corrected to the class expected at their position.
+pub struct PositionalFormat {
+    name: String,
+    pattern: Vec<char>,
+}
+
+impl PositionalFormat {
+    /// Spaces in `pattern` are ignored, like the spaces of the reads.
+    pub fn new(name: &str, pattern: &str) -> Result<Self, String> {
+        let pattern: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+        if pattern.is_empty() {
+            return Err(format!("Empty pattern for plate format {name}"));
+        }
+        if let Some(c) = pattern.iter().find(|c| !matches!(c, 'A' | '0' | '?')) {
+            return Err(format!(
+                "Invalid character '{c}' in the pattern of plate format {name}, expected A, 0 or ?"
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            pattern,
+        })
+    }
+
+    /// Current UK plates, e.g. `AB12 CDE`.
+    pub fn uk() -> Self {
+        Self::new("uk", "AA00AAA").unwrap()
+    }
+}
+
+impl PlateFormat for PositionalFormat {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&self, text: &str) -> Option<String> {
+        let text: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        if text.len() != self.pattern.len() {
+            return None;
+        }
+        text.iter()
+            .zip(self.pattern.iter())
+            .map(|(c, expected)| match expected {
+                'A' => to_letter(*c),
+                '0' => to_digit(*c),
+                _ => c.is_ascii_alphanumeric().then_some(*c),
+            })
+            .collect()
+    }
+}
+
+/// Format given as a regular expression over the read without spaces.
+/// Reads are only validated, the positions are unknown so confusables are not corrected.
+pub struct RegexFormat {
+    name: String,
+    regex: Regex,
+}
+
+impl RegexFormat {
+    pub fn new(name: &str, regex: &str) -> Result<Self, regex::Error> {
+        // The whole read must match, not a part of it.
+        let regex = Regex::new(&format!("^(?:{regex})$"))?;
+        Ok(Self {
+            name: name.to_string(),
+            regex,
+        })
+    }
+}
+
+impl PlateFormat for RegexFormat {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&self, text: &str) -> Option<String> {
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        self.regex.is_match(&text).then_some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positional_format_corrects_confusable_characters() {
+        let uk = PositionalFormat::uk();
+        let cases = [
+            ("AB12CDE", Some("AB12CDE")),
+            ("A812CDE", Some("AB12CDE")),
+            ("ABI2CDE", Some("AB12CDE")),
+            ("AB120DE", Some("AB12ODE")),
+            ("AB12 CDE", Some("AB12CDE")),
+            (" A B 1 2 C D E ", Some("AB12CDE")),
+            ("AB12CD", None),
+            ("AB12CDEF", None),
+            ("AB1XCDE", None),
+            ("AB12-DE", None),
+            ("", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(uk.read(text).as_deref(), expected, "read {text:?}");
+        }
+    }
+
+    #[test]
+    fn positional_format_wildcards_keep_the_read() {
+        let format = PositionalFormat::new("any", "?? 00").unwrap();
+        assert_eq!(format.read("X7 12").as_deref(), Some("X712"));
+        assert_eq!(format.read("X7 1Z").as_deref(), Some("X712"));
+        assert_eq!(format.read("-7 12"), None);
+    }
+
+    #[test]
+    fn positional_format_rejects_invalid_patterns() {
+        let cases = [
+            ("AA 00 AAA", true),
+            ("??0", true),
+            ("AB12", false),
+            ("aa00", false),
+            ("A-0", false),
+            ("", false),
+            ("   ", false),
+        ];
+        for (pattern, valid) in cases {
+            assert_eq!(
+                PositionalFormat::new("test", pattern).is_ok(),
+                valid,
+                "pattern {pattern:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn regex_format_matches_the_whole_read() {
+        // Without anchors each side of the alternation would match a part of longer reads.
+        let format = RegexFormat::new("pairs", "[A-Z]{2}|[0-9]{2}").unwrap();
+        let cases = [
+            ("AB", Some("AB")),
+            ("12", Some("12")),
+            ("AB12", None),
+            ("XAB", None),
+            ("123", None),
+            ("A 1", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(format.read(text).as_deref(), expected, "read {text:?}");
+        }
+    }
+
+    #[test]
+    fn regex_format_ignores_spaces() {
+        let format = RegexFormat::new("uk", "[A-Z]{2}[0-9]{2}[A-Z]{3}").unwrap();
+        assert_eq!(format.read("AB12 CDE").as_deref(), Some("AB12CDE"));
+        // Regex reads are not corrected.
+        assert_eq!(format.read("A812 CDE"), None);
+        assert!(RegexFormat::new("invalid", "[A-Z").is_err());
+    }
+}
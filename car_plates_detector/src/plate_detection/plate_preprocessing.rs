@@ -0,0 +1,106 @@
+use opencv::core::bitwise_not;
+use opencv::core::copy_make_border;
+use opencv::core::Point;
+use opencv::core::Scalar;
+use opencv::core::Size;
+use opencv::core::BORDER_DEFAULT;
+use opencv::core::BORDER_REPLICATE;
+use opencv::imgproc::adaptive_threshold;
+use opencv::imgproc::create_clahe;
+use opencv::imgproc::filter_2d;
+use opencv::imgproc::resize;
+use opencv::imgproc::threshold;
+use opencv::imgproc::ADAPTIVE_THRESH_GAUSSIAN_C;
+use opencv::imgproc::CLAHE;
+use opencv::imgproc::INTER_CUBIC;
+use opencv::imgproc::THRESH_BINARY;
+use opencv::imgproc::THRESH_OTSU;
+use opencv::prelude::Mat;
+
+/// Step applied to the grey plate crops before OCR. The steps of a reader run in order.
+#[derive(Clone, Debug)]
+pub enum PreprocessingStep {
+    /// Scales the crop by `factor`: the OCR reads small characters better once enlarged.
+    Upscale(f64),
+    /// Contrast Limited Adaptive Histogram Equalization on a grid of `tile_size` x `tile_size`.
+    Clahe { clip_limit: f64, tile_size: i32 },
+    /// Black and white, with the threshold chosen by Otsu's method.
+    OtsuThreshold,
+    /// Black and white, with a threshold per `block_size` neighbourhood (odd) lowered by `c`.
+    AdaptiveThreshold { block_size: i32, c: f64 },
+    /// 3x3 sharpening kernel, adding `delta` to every pixel.
+    Sharpen { delta: f64 },
+    /// Light text on a dark plate becomes dark text on a light plate.
+    Invert,
+    /// Extends the crop by `pixels` on every side, repeating its edges.
+    Padding(i32),
+}
+
+impl PreprocessingStep {
+    pub fn apply(&self, image: &Mat) -> opencv::Result<Mat> {
+        let mut processed = Mat::default();
+        match self {
+            PreprocessingStep::Upscale(factor) => resize(
+                image,
+                &mut processed,
+                Size::default(),
+                *factor,
+                *factor,
+                INTER_CUBIC,
+            )?,
+            PreprocessingStep::Clahe {
+                clip_limit,
+                tile_size,
+            } => {
+                let mut clahe = create_clahe(*clip_limit, Size::new(*tile_size, *tile_size))?;
+                clahe.apply(image, &mut processed)?;
+            }
+            PreprocessingStep::OtsuThreshold => {
+                threshold(
+                    image,
+                    &mut processed,
+                    0.0,
+                    255.0,
+                    THRESH_BINARY | THRESH_OTSU,
+                )?;
+            }
+            PreprocessingStep::AdaptiveThreshold { block_size, c } => adaptive_threshold(
+                image,
+                &mut processed,
+                255.0,
+                ADAPTIVE_THRESH_GAUSSIAN_C,
+                THRESH_BINARY,
+                *block_size,
+                *c,
+            )?,
+            PreprocessingStep::Sharpen { delta } => {
+                let kernel = Mat::from_slice_2d(&[
+                    [-1.0f32, -1.0, -1.0],
+                    [-1.0, 9.0, -1.0],
+                    [-1.0, -1.0, -1.0],
+                ])?;
+                filter_2d(
+                    image,
+                    &mut processed,
+                    -1,
+                    &kernel,
+                    Point::new(-1, -1),
+                    *delta,
+                    BORDER_DEFAULT,
+                )?
+            }
+            PreprocessingStep::Invert => bitwise_not(image, &mut processed, &Mat::default())?,
+            PreprocessingStep::Padding(pixels) => copy_make_border(
+                image,
+                &mut processed,
+                *pixels,
+                *pixels,
+                *pixels,
+                *pixels,
+                BORDER_REPLICATE,
+                Scalar::default(),
+            )?,
+        }
+        Ok(processed)
+    }
+}
@@ -0,0 +1,150 @@
+use opencv::core::Point;
+use opencv::core::Point2f;
+use opencv::core::Scalar;
+use opencv::core::Size;
+use opencv::core::Vector;
+use opencv::core::BORDER_REPLICATE;
+use opencv::core::DECOMP_LU;
+use opencv::imgcodecs::imwrite;
+use opencv::imgproc::approx_poly_dp;
+use opencv::imgproc::arc_length;
+use opencv::imgproc::contour_area;
+use opencv::imgproc::find_contours;
+use opencv::imgproc::get_perspective_transform;
+use opencv::imgproc::is_contour_convex;
+use opencv::imgproc::min_area_rect;
+use opencv::imgproc::threshold;
+use opencv::imgproc::warp_perspective;
+use opencv::imgproc::CHAIN_APPROX_SIMPLE;
+use opencv::imgproc::INTER_LINEAR;
+use opencv::imgproc::RETR_EXTERNAL;
+use opencv::imgproc::THRESH_BINARY;
+use opencv::imgproc::THRESH_OTSU;
+use opencv::prelude::Mat;
+use opencv::prelude::MatTraitConst;
+
+/// Straightens skewed plates: the plate outline is searched in the grey crop of its box and
+/// warped to an upright image of `size` for each line of the plate.
+pub struct PlateRectifier {
+    size: Size,
+    /// Smallest part of the crop the plate outline must cover.
+    min_coverage: f64,
+    /// Folder the warped plates are written to, for debugging.
+    debug_dir: Option<String>,
+}
+
+impl PlateRectifier {
+    pub fn new(size: Size) -> Self {
+        Self {
+            size,
+            min_coverage: 0.3,
+            debug_dir: None,
+        }
+    }
+
+    /// Writes every warped plate as a PNG in `debug_dir`, created when missing.
+    pub fn with_debug_dir(mut self, debug_dir: &str) -> Self {
+        if let Err(e) = std::fs::create_dir_all(debug_dir) {
+            println!("Cannot create {debug_dir}: {:?}", e);
+        }
+        self.debug_dir = Some(debug_dir.to_string());
+        self
+    }
+
+    /// Returns the warped plate of `lines` lines, or `None` when no plate outline is found in
+    /// `crop`. `name` identifies the plate in the debug files.
+    pub fn rectify(&self, crop: &Mat, lines: i32, name: &str) -> Option<Mat> {
+        let corners = self.find_corners(crop)?;
+        let size = Size::new(self.size.width, self.size.height * lines.max(1));
+        let target = Vector::<Point2f>::from_iter([
+            Point2f::new(0.0, 0.0),
+            Point2f::new(size.width as f32, 0.0),
+            Point2f::new(size.width as f32, size.height as f32),
+            Point2f::new(0.0, size.height as f32),
+        ]);
+        let transform = get_perspective_transform(&corners, &target, DECOMP_LU).ok()?;
+
+        let mut warped = Mat::default();
+        warp_perspective(
+            crop,
+            &mut warped,
+            &transform,
+            size,
+            INTER_LINEAR,
+            BORDER_REPLICATE,
+            Scalar::default(),
+        )
+        .ok()?;
+
+        if let Some(debug_dir) = &self.debug_dir {
+            let path = format!("{debug_dir}/{name}.png");
+            if let Err(e) = imwrite(&path, &warped, &Vector::new()) {
+                println!("Cannot write {path}: {:?}", e);
+            }
+        }
+        Some(warped)
+    }
+
+    /// Corners of the largest bright blob of the crop, the plate background, ordered top left,
+    /// top right, bottom right and bottom left. The outline is simplified to a quadrilateral when
+    /// possible, to undo the perspective, and replaced by its rotated bounding box otherwise.
+    fn find_corners(&self, crop: &Mat) -> Option<Vector<Point2f>> {
+        let mut binary = Mat::default();
+        threshold(crop, &mut binary, 0.0, 255.0, THRESH_BINARY | THRESH_OTSU).ok()?;
+
+        let mut contours = Vector::<Vector<Point>>::new();
+        find_contours(
+            &binary,
+            &mut contours,
+            RETR_EXTERNAL,
+            CHAIN_APPROX_SIMPLE,
+            Point::default(),
+        )
+        .ok()?;
+
+        let (contour, area) = contours
+            .iter()
+            .map(|contour| {
+                let area = contour_area(&contour, false).unwrap_or(0.0);
+                (contour, area)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        if area < self.min_coverage * (crop.cols() * crop.rows()) as f64 {
+            return None;
+        }
+
+        let mut outline = Vector::<Point>::new();
+        let perimeter = arc_length(&contour, true).ok()?;
+        approx_poly_dp(&contour, &mut outline, 0.02 * perimeter, true).ok()?;
+
+        let mut corners: Vec<Point2f> =
+            if outline.len() == 4 && is_contour_convex(&outline).unwrap_or(false) {
+                outline
+                    .iter()
+                    .map(|p| Point2f::new(p.x as f32, p.y as f32))
+                    .collect()
+            } else {
+                let mut points = [Point2f::default(); 4];
+                min_area_rect(&contour).ok()?.points(&mut points).ok()?;
+                points.to_vec()
+            };
+        order_corners(&mut corners);
+        Some(Vector::from_iter(corners))
+    }
+}
+
+/// Sorts 4 corners as top left, top right, bottom right and bottom left.
+fn order_corners(corners: &mut [Point2f]) {
+    let by = |key: fn(&Point2f) -> f32| {
+        corners
+            .iter()
+            .cloned()
+            .min_by(|a, b| key(a).total_cmp(&key(b)))
+            .unwrap()
+    };
+    let top_left = by(|p| p.x + p.y);
+    let bottom_right = by(|p| -(p.x + p.y));
+    let top_right = by(|p| p.y - p.x);
+    let bottom_left = by(|p| p.x - p.y);
+    corners.copy_from_slice(&[top_left, top_right, bottom_right, bottom_left]);
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use rusted_pipe::channels::read_channel::InputGenerator;
+use rusted_pipe::channels::typed_read_channel::ReadChannel2;
+use rusted_pipe::channels::typed_write_channel::WriteChannel1;
+use rusted_pipe::graph::processor::Processor;
+use rusted_pipe::graph::processor::ProcessorWriter;
+use rusted_pipe::RustedPipeError;
+
+use super::count_frame;
+use super::tracker::Track;
+use super::tracker::TrackState;
+use super::CarWithText;
+use super::Detection;
+
+/// Reads collected for one track.
+struct TrackReads {
+    reads: Vec<(String, f32)>,
+    /// Last plate and car boxes of the track with a read.
+    plate: Detection,
+    car: Detection,
+}
+
+/// Character by character vote over `reads`, weighted by their confidence.
+/// Only the reads of the most voted length take part. The confidence of the result is the
+/// share of the votes won by each character, averaged over the positions, times the share
+/// of the votes won by the length.
+pub fn vote(reads: &[(String, f32)]) -> Option<(String, f32)> {
+    let weight = |confidence: f32| confidence.max(0.01);
+
+    let mut lengths = HashMap::new();
+    let mut total = 0.0;
+    for (text, confidence) in reads {
+        if text.is_empty() {
+            continue;
+        }
+        *lengths.entry(text.chars().count()).or_insert(0.0) += weight(*confidence);
+        total += weight(*confidence);
+    }
+    let (length, length_weight) = lengths
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))?;
+
+    let mut positions = vec![HashMap::new(); length];
+    for (text, confidence) in reads {
+        if text.chars().count() != length {
+            continue;
+        }
+        for (position, c) in text.chars().enumerate() {
+            *positions[position].entry(c).or_insert(0.0) += weight(*confidence);
+        }
+    }
+
+    let mut consensus = String::new();
+    let mut agreement = 0.0;
+    for votes in positions {
+        let (c, best) = votes
+            .into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)))?;
+        consensus.push(c);
+        agreement += best / length_weight;
+    }
+    let confidence = agreement / length as f32 * length_weight / total;
+    Some((consensus, confidence))
+}
+
+/// Accumulates the OCR reads of each car track and replaces the text read in a single frame
+/// with the consensus of the track. Plates are emitted once their track has `min_votes` reads,
+/// and one last time in the frame the track ends.
+pub struct PlateVoting {
+    node_id: String,
+    min_votes: usize,
+    tracks: HashMap<u64, TrackReads>,
+}
+
+impl PlateVoting {
+    pub fn new(min_votes: usize) -> Self {
+        Self {
+            node_id: "plate_voting".to_string(),
+            min_votes,
+            tracks: HashMap::new(),
+        }
+    }
+
+    /// Id of the graph node, labelling the frames counted by the voting.
+    pub fn with_node_id(mut self, node_id: &str) -> Self {
+        self.node_id = node_id.to_string();
+        self
+    }
+
+    pub fn update(&mut self, tracks: &[Track], plates: Vec<CarWithText>) -> Vec<CarWithText> {
+        // Plates and tracks are paired through the index of the car detection they share.
+        for plate in plates {
+            if plate.rejected {
+                continue;
+            }
+            let (text, car, car_index) = match (&plate.text, &plate.car, plate.car_index) {
+                (Some(text), Some(car), Some(car_index)) => (text, car, car_index),
+                _ => continue,
+            };
+            let track = tracks
+                .iter()
+                .find(|track| track.detection_index == Some(car_index));
+            if let Some(track) = track {
+                let entry = self.tracks.entry(track.id).or_insert(TrackReads {
+                    reads: vec![],
+                    plate: plate.plate.clone(),
+                    car: car.clone(),
+                });
+                entry.reads.push((text.clone(), plate.text_confidence));
+                entry.plate = plate.plate.clone();
+                entry.car = car.clone();
+            }
+        }
+
+        let mut out = vec![];
+        for track in tracks {
+            let ended = track.state == TrackState::Ended;
+            let reads = match self.tracks.get(&track.id) {
+                Some(reads) if ended || reads.reads.len() >= self.min_votes => reads,
+                _ => continue,
+            };
+            if let Some((text, confidence)) = vote(&reads.reads) {
+                if ended {
+                    println!(
+                        "Track {} plate {} ({:.2}, {} reads)",
+                        track.id,
+                        text,
+                        confidence,
+                        reads.reads.len()
+                    );
+                }
+                // Lost tracks keep the last car detection, moved to the predicted box.
+                let mut car = track.detection.clone().unwrap_or_else(|| reads.car.clone());
+                car.rect = track.rect;
+                out.push(CarWithText {
+                    car: Some(car),
+                    car_index: track.detection_index,
+                    plate: reads.plate.clone(),
+                    text: Some(text),
+                    text_confidence: confidence,
+                    char_confidences: vec![],
+                    format: None,
+                    rejected: false,
+                    track_id: Some(track.id),
+                });
+            }
+            if ended {
+                self.tracks.remove(&track.id);
+            }
+        }
+        out
+    }
+}
+
+impl Default for PlateVoting {
+    fn default() -> Self {
+        Self::new(5)
+    }
+}
+
+impl Processor for PlateVoting {
+    type INPUT = ReadChannel2<Vec<Track>, Vec<CarWithText>>;
+    type OUTPUT = WriteChannel1<Vec<CarWithText>>;
+    fn handle(
+        &mut self,
+        mut input: <Self::INPUT as InputGenerator>::INPUT,
+        mut output: ProcessorWriter<Self::OUTPUT>,
+    ) -> Result<(), RustedPipeError> {
+        let tracks = input.c1_owned().unwrap();
+        println!("Plate voting {}", tracks.version.timestamp_ns);
+        let plates = input
+            .c2_owned()
+            .map(|plates| plates.data)
+            .unwrap_or_default();
+
+        let out = self.update(&tracks.data, plates);
+
+        output.writer.c1().write(out, &tracks.version).unwrap();
+        count_frame(&self.node_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opencv::core::Rect;
+
+    fn reads(reads: &[(&str, f32)]) -> Vec<(String, f32)> {
+        reads
+            .iter()
+            .map(|(text, confidence)| (text.to_string(), *confidence))
+            .collect()
+    }
+
+    #[test]
+    fn vote_keeps_the_majority_of_each_character() {
+        let (text, confidence) = vote(&reads(&[
+            ("AB12", 0.9),
+            ("A812", 0.5),
+            ("AB12", 0.8),
+            ("AB17", 0.3),
+        ]))
+        .unwrap();
+        assert_eq!(text, "AB12");
+        // B wins 2.0 of the 2.5 votes of the second character, 2 wins 2.2 of the fourth.
+        assert!((confidence - (1.0 + 0.8 + 1.0 + 0.88) / 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn vote_ties_go_to_the_lowest_character() {
+        let (text, confidence) = vote(&reads(&[("AB12", 0.6), ("A812", 0.6)])).unwrap();
+        assert_eq!(text, "A812");
+        assert!((confidence - 0.875).abs() < 1e-5);
+    }
+
+    #[test]
+    fn vote_only_counts_the_reads_of_the_most_voted_length() {
+        let (text, confidence) = vote(&reads(&[
+            ("AB12", 0.9),
+            ("AB123", 0.4),
+            ("AB12", 0.5),
+            ("", 0.9),
+        ]))
+        .unwrap();
+        assert_eq!(text, "AB12");
+        // The reads of 4 characters agree and hold 1.4 of the 1.8 votes.
+        assert!((confidence - 1.4 / 1.8).abs() < 1e-5);
+
+        // Length ties go to the longest reads.
+        let (text, _) = vote(&reads(&[("AB12", 0.5), ("AB123", 0.5)])).unwrap();
+        assert_eq!(text, "AB123");
+    }
+
+    #[test]
+    fn vote_needs_a_non_empty_read() {
+        assert_eq!(vote(&[]), None);
+        assert_eq!(vote(&reads(&[("", 0.9)])), None);
+    }
+
+    fn car() -> Detection {
+        Detection::new(Rect::new(0, 100, 100, 80), 2, 0.9)
+    }
+
+    fn track(state: TrackState, detection_index: Option<usize>) -> Track {
+        Track {
+            id: 7,
+            rect: Rect::new(10, 100, 100, 80),
+            detection: detection_index.map(|_| car()),
+            detection_index,
+            age: 3,
+            hits: 3,
+            state,
+        }
+    }
+
+    fn read(text: &str, confidence: f32) -> CarWithText {
+        let plate = Detection::new(Rect::new(30, 150, 40, 10), 0, 0.8);
+        let mut read = CarWithText::new(Some(text.to_string()), confidence, plate);
+        read.car = Some(car());
+        read.car_index = Some(1);
+        read
+    }
+
+    #[test]
+    fn emits_the_consensus_once_the_track_has_min_votes_reads() {
+        let mut voting = PlateVoting::new(2);
+        let tracks = [track(TrackState::Confirmed, Some(1))];
+        assert!(voting.update(&tracks, vec![read("A812", 0.4)]).is_empty());
+
+        let out = voting.update(&tracks, vec![read("AB12", 0.9)]);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].text.as_deref(), Some("AB12"));
+        assert_eq!(out[0].track_id, Some(7));
+        assert_eq!(out[0].car_index, Some(1));
+
+        // Plates of other cars of the frame are not counted.
+        let mut other_car = read("XY99", 0.9);
+        other_car.car_index = Some(0);
+        let out = voting.update(&tracks, vec![other_car]);
+        assert_eq!(out[0].text.as_deref(), Some("AB12"));
+    }
+
+    #[test]
+    fn flushes_the_reads_of_an_ended_track() {
+        let mut voting = PlateVoting::new(5);
+        let tracks = [track(TrackState::Confirmed, Some(1))];
+        assert!(voting.update(&tracks, vec![read("AB12", 0.9)]).is_empty());
+        let mut rejected = read("A8I2", 0.9);
+        rejected.rejected = true;
+        assert!(voting.update(&tracks, vec![rejected]).is_empty());
+
+        // Emitted once in the frame the track ends, before min_votes, at the predicted box.
+        let out = voting.update(&[track(TrackState::Ended, None)], vec![]);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].text.as_deref(), Some("AB12"));
+        assert_eq!(
+            out[0].car.as_ref().unwrap().rect,
+            Rect::new(10, 100, 100, 80)
+        );
+        assert_eq!(out[0].car_index, None);
+
+        assert!(voting
+            .update(&[track(TrackState::Ended, None)], vec![])
+            .is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+use opencv::core::Point;
+use opencv::core::Rect;
+use opencv::core::Scalar;
+use opencv::core::Vector;
+use opencv::imgproc::polylines;
+use opencv::imgproc::LINE_8;
+use opencv::prelude::Mat;
+
+/// Polygon zones of the frame where detections are kept.
+/// A point is active when it lies inside any include zone, or there is no include zone,
+/// and outside every exclude zone. Coordinates are frame pixels.
+#[derive(Clone, Debug, Default)]
+pub struct RegionsOfInterest {
+    include: Vec<Vec<Point>>,
+    exclude: Vec<Vec<Point>>,
+}
+
+impl RegionsOfInterest {
+    pub fn include(mut self, polygon: Vec<Point>) -> Self {
+        self.include.push(polygon);
+        self
+    }
+
+    pub fn exclude(mut self, polygon: Vec<Point>) -> Self {
+        self.exclude.push(polygon);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        let included =
+            self.include.is_empty() || self.include.iter().any(|zone| inside(zone, point));
+        included && !self.exclude.iter().any(|zone| inside(zone, point))
+    }
+
+    /// Whether the centre of `rect` is active.
+    pub fn accepts(&self, rect: &Rect) -> bool {
+        self.contains(Point::new(
+            rect.x + rect.width / 2,
+            rect.y + rect.height / 2,
+        ))
+    }
+
+    /// Outlines include zones in green and exclude zones in red.
+    pub fn draw(&self, image: &mut Mat) {
+        let zones = [
+            (&self.include, Scalar::from((0.0, 255.0, 0.0))),
+            (&self.exclude, Scalar::from((0.0, 0.0, 255.0))),
+        ];
+        for (polygons, color) in zones {
+            if polygons.is_empty() {
+                continue;
+            }
+            let contours: Vector<Vector<Point>> = polygons
+                .iter()
+                .map(|polygon| polygon.iter().copied().collect())
+                .collect();
+            polylines(image, &contours, true, color, 2, LINE_8, 0).unwrap();
+        }
+    }
+}
+
+/// Even-odd rule point in polygon test.
+fn inside(polygon: &[Point], point: Point) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len().wrapping_sub(1);
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.y > point.y) != (b.y > point.y) {
+            let x = a.x as f32 + (point.y - a.y) as f32 * (b.x - a.x) as f32 / (b.y - a.y) as f32;
+            if (point.x as f32) < x {
+                inside = !inside;
+            }
+        }
+        j = i;
+    }
+    inside
+}
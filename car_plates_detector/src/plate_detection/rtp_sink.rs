@@ -39,6 +39,11 @@ pub fn create_caps(width: usize, height: usize, fps: usize) -> Caps {
 
 impl RtpSink {
     pub fn new(fps: usize, host: &str, port: usize) -> Self {
+        Self::with_size(fps, host, port, 1280, 720)
+    }
+
+    /// Streams frames of `width`x`height`, which must match the size of the incoming frames.
+    pub fn with_size(fps: usize, host: &str, port: usize, width: usize, height: usize) -> Self {
         gstreamer::init().unwrap();
         // let pipeline_str = format!(
         //     "appsrc ! videoconvert ! x264enc ! mpegtsmux ! filesink location=file.mp4"
@@ -56,7 +61,7 @@ impl RtpSink {
             .and_dynamic_cast::<gstreamer_app::AppSrc>()
             .expect("Cannot create AppSrc");
 
-        app_src.set_caps(Some(&create_caps(1280, 720, fps)));
+        app_src.set_caps(Some(&create_caps(width, height, fps)));
         app_src.set_format(gstreamer::Format::Time);
         let id = "rtp_sink".to_string();
 
@@ -64,7 +69,7 @@ impl RtpSink {
             .set_state(gstreamer::State::Playing)
             .expect("Unable to set the pipeline to the `Playing` state");
 
-        let buffer = Buffer::with_size(1280 * 720 * 3).expect("Cannot create gst buffer");
+        let buffer = Buffer::with_size(width * height * 3).expect("Cannot create gst buffer");
         let (buffer_s, buffer_r) = channel();
         let sink = Self {
             id,
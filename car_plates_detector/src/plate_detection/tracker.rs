@@ -0,0 +1,544 @@
+use opencv::core::Rect;
+use rusted_pipe::channels::read_channel::InputGenerator;
+use rusted_pipe::channels::typed_read_channel::ReadChannel1;
+use rusted_pipe::channels::typed_write_channel::WriteChannel1;
+use rusted_pipe::graph::processor::Processor;
+use rusted_pipe::graph::processor::ProcessorWriter;
+use rusted_pipe::RustedPipeError;
+
+use super::count_frame;
+use super::Detection;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackState {
+    /// Not matched for enough frames yet to be trusted.
+    Tentative,
+    /// Matched in this frame.
+    Confirmed,
+    /// Not matched in this frame, the box is the prediction of the filter.
+    Lost,
+    /// Lost for too long. Only emitted once, in the frame the track is removed.
+    Ended,
+}
+
+/// An object followed across frames.
+#[derive(Clone, Debug)]
+pub struct Track {
+    /// Unique for the lifetime of the tracker.
+    pub id: u64,
+    /// Estimated box of the object in this frame.
+    pub rect: Rect,
+    /// Detection matched with the track in this frame, if any.
+    pub detection: Option<Detection>,
+    /// Index of `detection` in the detections of the frame.
+    pub detection_index: Option<usize>,
+    /// Frames since the track was created.
+    pub age: u32,
+    /// Frames in which the track was matched.
+    pub hits: u32,
+    pub state: TrackState,
+}
+
+/// Minimal matrix helpers for the Kalman filter.
+type Matrix<const R: usize, const C: usize> = [[f32; C]; R];
+
+fn mul<const R: usize, const K: usize, const C: usize>(
+    a: &Matrix<R, K>,
+    b: &Matrix<K, C>,
+) -> Matrix<R, C> {
+    let mut out = [[0.0; C]; R];
+    for r in 0..R {
+        for c in 0..C {
+            out[r][c] = (0..K).map(|k| a[r][k] * b[k][c]).sum();
+        }
+    }
+    out
+}
+
+fn transpose<const R: usize, const C: usize>(a: &Matrix<R, C>) -> Matrix<C, R> {
+    let mut out = [[0.0; R]; C];
+    for r in 0..R {
+        for c in 0..C {
+            out[c][r] = a[r][c];
+        }
+    }
+    out
+}
+
+fn identity<const N: usize>() -> Matrix<N, N> {
+    let mut out = [[0.0; N]; N];
+    for (i, row) in out.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    out
+}
+
+fn diagonal<const N: usize>(values: [f32; N]) -> Matrix<N, N> {
+    let mut out = [[0.0; N]; N];
+    for (i, value) in values.into_iter().enumerate() {
+        out[i][i] = value;
+    }
+    out
+}
+
+/// Gauss-Jordan inverse, `None` when the matrix is singular.
+fn inverse<const N: usize>(a: &Matrix<N, N>) -> Option<Matrix<N, N>> {
+    let mut a = *a;
+    let mut out = identity::<N>();
+    for col in 0..N {
+        let pivot = (col..N).max_by(|x, y| a[*x][col].abs().total_cmp(&a[*y][col].abs()))?;
+        if a[pivot][col].abs() < f32::EPSILON {
+            return None;
+        }
+        a.swap(col, pivot);
+        out.swap(col, pivot);
+
+        let scale = a[col][col];
+        for c in 0..N {
+            a[col][c] /= scale;
+            out[col][c] /= scale;
+        }
+        for r in 0..N {
+            if r != col {
+                let factor = a[r][col];
+                for c in 0..N {
+                    a[r][c] -= factor * a[col][c];
+                    out[r][c] -= factor * out[col][c];
+                }
+            }
+        }
+    }
+    Some(out)
+}
+
+/// Constant velocity filter of SORT on `[cx, cy, area, aspect ratio]`, the aspect ratio
+/// being constant. The state is `[cx, cy, area, ratio, vx, vy, v_area]`.
+struct KalmanBox {
+    x: [f32; 7],
+    p: Matrix<7, 7>,
+}
+
+impl KalmanBox {
+    fn new(rect: &Rect) -> Self {
+        let z = measurement(rect);
+        Self {
+            x: [z[0], z[1], z[2], z[3], 0.0, 0.0, 0.0],
+            // High uncertainty on the velocities, which are not observed.
+            p: diagonal([10.0, 10.0, 10.0, 10.0, 1e4, 1e4, 1e4]),
+        }
+    }
+
+    fn transition() -> Matrix<7, 7> {
+        let mut f = identity::<7>();
+        f[0][4] = 1.0;
+        f[1][5] = 1.0;
+        f[2][6] = 1.0;
+        f
+    }
+
+    fn observation() -> Matrix<4, 7> {
+        let mut h = [[0.0; 7]; 4];
+        for (i, row) in h.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        h
+    }
+
+    fn predict(&mut self) {
+        // The area cannot become negative.
+        if self.x[2] + self.x[6] <= 0.0 {
+            self.x[6] = 0.0;
+        }
+        let f = Self::transition();
+        let x = mul(&f, &self.x.map(|v| [v]));
+        self.x = x.map(|[v]| v);
+
+        let q = diagonal([1.0, 1.0, 1.0, 1.0, 0.01, 0.01, 0.0001]);
+        let p = mul(&mul(&f, &self.p), &transpose(&f));
+        for r in 0..7 {
+            for c in 0..7 {
+                self.p[r][c] = p[r][c] + q[r][c];
+            }
+        }
+    }
+
+    fn update(&mut self, rect: &Rect) {
+        let z = measurement(rect);
+        let h = Self::observation();
+        let r = diagonal([1.0, 1.0, 10.0, 10.0]);
+
+        let ht = transpose(&h);
+        let mut s = mul(&mul(&h, &self.p), &ht);
+        for i in 0..4 {
+            for j in 0..4 {
+                s[i][j] += r[i][j];
+            }
+        }
+        let s_inv = match inverse(&s) {
+            Some(s_inv) => s_inv,
+            None => return,
+        };
+        let k = mul(&mul(&self.p, &ht), &s_inv);
+
+        let mut y = [[0.0]; 4];
+        for i in 0..4 {
+            y[i][0] = z[i] - self.x[i];
+        }
+        let correction = mul(&k, &y);
+        for i in 0..7 {
+            self.x[i] += correction[i][0];
+        }
+
+        let mut i_kh = identity::<7>();
+        let kh = mul(&k, &h);
+        for r in 0..7 {
+            for c in 0..7 {
+                i_kh[r][c] -= kh[r][c];
+            }
+        }
+        self.p = mul(&i_kh, &self.p);
+    }
+
+    fn rect(&self) -> Rect {
+        let area = self.x[2].max(0.0);
+        let ratio = self.x[3].max(f32::EPSILON);
+        let width = (area * ratio).sqrt();
+        let height = if width > 0.0 { area / width } else { 0.0 };
+        Rect::new(
+            (self.x[0] - width / 2.0) as i32,
+            (self.x[1] - height / 2.0) as i32,
+            width as i32,
+            height as i32,
+        )
+    }
+}
+
+fn measurement(rect: &Rect) -> [f32; 4] {
+    let width = rect.width as f32;
+    let height = rect.height.max(1) as f32;
+    [
+        rect.x as f32 + width / 2.0,
+        rect.y as f32 + height / 2.0,
+        width * height,
+        width / height,
+    ]
+}
+
+fn iou(a: &Rect, b: &Rect) -> f32 {
+    let left = a.x.max(b.x);
+    let top = a.y.max(b.y);
+    let right = (a.x + a.width).min(b.x + b.width);
+    let bottom = (a.y + a.height).min(b.y + b.height);
+    if right <= left || bottom <= top {
+        return 0.0;
+    }
+    let intersection = ((right - left) * (bottom - top)) as f32;
+    let union = (a.width * a.height + b.width * b.height) as f32 - intersection;
+    intersection / union
+}
+
+struct TrackedObject {
+    id: u64,
+    filter: KalmanBox,
+    age: u32,
+    hits: u32,
+    /// Consecutive frames matched, resets when the track is lost.
+    streak: u32,
+    /// Matched for `min_hits` consecutive frames at least once.
+    confirmed: bool,
+    time_since_update: u32,
+}
+
+/// SORT style tracker: a Kalman filter predicts the box of every track in the next frame and
+/// detections are matched to the predictions by IoU, best overlaps first.
+pub struct Tracker {
+    node_id: String,
+    tracks: Vec<TrackedObject>,
+    next_id: u64,
+    last_version: Option<u128>,
+    max_age: u32,
+    min_hits: u32,
+    iou_threshold: f32,
+}
+
+impl Tracker {
+    /// A track ends after `max_age` frames without a match and is confirmed after `min_hits`
+    /// consecutive matches. Detections are only matched to tracks overlapping more than
+    /// `iou_threshold`.
+    pub fn new(max_age: u32, min_hits: u32, iou_threshold: f32) -> Self {
+        Self {
+            node_id: "tracker".to_string(),
+            tracks: vec![],
+            next_id: 1,
+            last_version: None,
+            max_age,
+            min_hits,
+            iou_threshold,
+        }
+    }
+
+    /// Id of the graph node, labelling the frames counted by the tracker.
+    pub fn with_node_id(mut self, node_id: &str) -> Self {
+        self.node_id = node_id.to_string();
+        self
+    }
+
+    /// Updates the tracks with the `detections` of the frame versioned `timestamp_ns`. The filter
+    /// steps one frame at a time, frames older than the last one would move the tracks backwards:
+    /// they get the tracks as they are, matched with none of their detections.
+    pub fn update_at(&mut self, timestamp_ns: u128, detections: Vec<Detection>) -> Vec<Track> {
+        if let Some(last_version) = self.last_version {
+            if timestamp_ns <= last_version {
+                println!("Out of order detections {}", timestamp_ns);
+                return self.current_tracks();
+            }
+        }
+        self.last_version = Some(timestamp_ns);
+        self.update(detections)
+    }
+
+    /// Tracks at their last estimated box, without any detection.
+    fn current_tracks(&self) -> Vec<Track> {
+        self.tracks
+            .iter()
+            .map(|track| Track {
+                id: track.id,
+                rect: track.filter.rect(),
+                detection: None,
+                detection_index: None,
+                age: track.age,
+                hits: track.hits,
+                state: if track.confirmed {
+                    TrackState::Lost
+                } else {
+                    TrackState::Tentative
+                },
+            })
+            .collect()
+    }
+
+    /// Advances every track by one frame and matches them with `detections`.
+    pub fn update(&mut self, detections: Vec<Detection>) -> Vec<Track> {
+        for track in self.tracks.iter_mut() {
+            track.filter.predict();
+            track.age += 1;
+            track.time_since_update += 1;
+        }
+
+        let mut pairs = vec![];
+        for (t, track) in self.tracks.iter().enumerate() {
+            let predicted = track.filter.rect();
+            for (d, detection) in detections.iter().enumerate() {
+                let overlap = iou(&predicted, &detection.rect);
+                if overlap > self.iou_threshold {
+                    pairs.push((overlap, t, d));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut matches: Vec<Option<usize>> = vec![None; self.tracks.len()];
+        let mut detection_taken = vec![false; detections.len()];
+        for (_, t, d) in pairs {
+            if matches[t].is_some() || detection_taken[d] {
+                continue;
+            }
+            detection_taken[d] = true;
+            matches[t] = Some(d);
+        }
+
+        let mut out = vec![];
+        let mut kept = vec![];
+        for (track, detection_index) in self.tracks.drain(..).zip(matches) {
+            let mut track = track;
+            let matched = detection_index.map(|d| detections[d].clone());
+            let state = match &matched {
+                Some(detection) => {
+                    track.filter.update(&detection.rect);
+                    track.hits += 1;
+                    track.streak += 1;
+                    track.time_since_update = 0;
+                    track.confirmed |= track.streak >= self.min_hits;
+                    if track.confirmed {
+                        TrackState::Confirmed
+                    } else {
+                        TrackState::Tentative
+                    }
+                }
+                None => {
+                    track.streak = 0;
+                    if track.time_since_update > self.max_age {
+                        TrackState::Ended
+                    } else if track.confirmed {
+                        TrackState::Lost
+                    } else {
+                        TrackState::Tentative
+                    }
+                }
+            };
+            out.push(Track {
+                id: track.id,
+                rect: matched
+                    .as_ref()
+                    .map(|detection| detection.rect)
+                    .unwrap_or_else(|| track.filter.rect()),
+                detection: matched,
+                detection_index,
+                age: track.age,
+                hits: track.hits,
+                state,
+            });
+            if state != TrackState::Ended {
+                kept.push(track);
+            }
+        }
+        self.tracks = kept;
+
+        for (d, (detection, taken)) in detections.into_iter().zip(detection_taken).enumerate() {
+            if taken {
+                continue;
+            }
+            let track = TrackedObject {
+                id: self.next_id,
+                filter: KalmanBox::new(&detection.rect),
+                age: 0,
+                hits: 1,
+                streak: 1,
+                confirmed: self.min_hits <= 1,
+                time_since_update: 0,
+            };
+            self.next_id += 1;
+            out.push(Track {
+                id: track.id,
+                rect: detection.rect,
+                detection: Some(detection),
+                detection_index: Some(d),
+                age: 0,
+                hits: 1,
+                state: if track.confirmed {
+                    TrackState::Confirmed
+                } else {
+                    TrackState::Tentative
+                },
+            });
+            self.tracks.push(track);
+        }
+        out
+    }
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Self::new(5, 3, 0.3)
+    }
+}
+
+impl Processor for Tracker {
+    type INPUT = ReadChannel1<Vec<Detection>>;
+    type OUTPUT = WriteChannel1<Vec<Track>>;
+    fn handle(
+        &mut self,
+        mut input: <Self::INPUT as InputGenerator>::INPUT,
+        mut output: ProcessorWriter<Self::OUTPUT>,
+    ) -> Result<(), RustedPipeError> {
+        let detections = input.c1_owned().unwrap();
+        let version = detections.version;
+        println!("Tracking {}", version.timestamp_ns);
+
+        let tracks = self.update_at(version.timestamp_ns, detections.data);
+
+        output.writer.c1().write(tracks, &version).unwrap();
+        count_frame(&self.node_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn car(x: i32) -> Detection {
+        Detection::new(Rect::new(x, 100, 100, 80), 2, 0.9)
+    }
+
+    #[test]
+    fn keeps_the_id_through_a_short_occlusion() {
+        let mut tracker = Tracker::new(3, 1, 0.3);
+        let id = tracker.update(vec![car(0)])[0].id;
+        for frame in 1..5 {
+            let tracks = tracker.update(vec![car(frame * 10)]);
+            assert_eq!(tracks.len(), 1);
+            assert_eq!((tracks[0].id, tracks[0].state), (id, TrackState::Confirmed));
+        }
+
+        // Hidden for two frames, the box follows the prediction of the filter.
+        for _ in 0..2 {
+            let tracks = tracker.update(vec![]);
+            assert_eq!(tracks.len(), 1);
+            assert_eq!((tracks[0].id, tracks[0].state), (id, TrackState::Lost));
+            assert!(tracks[0].detection.is_none());
+        }
+
+        let tracks = tracker.update(vec![car(1000), car(70)]);
+        let track = tracks.iter().find(|track| track.id == id).unwrap();
+        assert_eq!(track.state, TrackState::Confirmed);
+        assert_eq!(track.detection_index, Some(1));
+        assert_eq!(track.rect, car(70).rect);
+    }
+
+    #[test]
+    fn confirms_after_min_hits_and_ends_after_max_age() {
+        let mut tracker = Tracker::new(2, 3, 0.3);
+        let states: Vec<TrackState> = (0..3)
+            .map(|_| tracker.update(vec![car(0)])[0].state)
+            .collect();
+        assert_eq!(
+            states,
+            [
+                TrackState::Tentative,
+                TrackState::Tentative,
+                TrackState::Confirmed
+            ]
+        );
+
+        let states: Vec<TrackState> = (0..3).map(|_| tracker.update(vec![])[0].state).collect();
+        assert_eq!(
+            states,
+            [TrackState::Lost, TrackState::Lost, TrackState::Ended]
+        );
+        assert!(tracker.update(vec![]).is_empty());
+
+        // A new detection starts a new track.
+        let tracks = tracker.update(vec![car(0)]);
+        assert_eq!((tracks[0].id, tracks[0].state), (2, TrackState::Tentative));
+    }
+
+    #[test]
+    fn unconfirmed_tracks_stay_tentative_until_they_end() {
+        let mut tracker = Tracker::new(1, 3, 0.3);
+        tracker.update(vec![car(0)]);
+        let tracks = tracker.update(vec![]);
+        assert_eq!(tracks[0].state, TrackState::Tentative);
+        let tracks = tracker.update(vec![]);
+        assert_eq!(tracks[0].state, TrackState::Ended);
+        assert!(tracker.update(vec![]).is_empty());
+    }
+
+    #[test]
+    fn out_of_order_frames_get_the_current_tracks() {
+        let mut tracker = Tracker::new(3, 1, 0.3);
+        let id = tracker.update_at(2_000, vec![car(0)])[0].id;
+
+        for timestamp_ns in [1_000, 2_000] {
+            let tracks = tracker.update_at(timestamp_ns, vec![car(500)]);
+            assert_eq!(tracks.len(), 1);
+            assert_eq!((tracks[0].id, tracks[0].state), (id, TrackState::Lost));
+            assert_eq!(tracks[0].detection_index, None);
+        }
+
+        // The older frames did not advance the tracks.
+        let tracks = tracker.update_at(3_000, vec![car(10)]);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!((tracks[0].id, tracks[0].age), (id, 1));
+    }
+}
@@ -1,14 +1,19 @@
+use opencv::core::Scalar;
 use opencv::core::Size;
 
-use opencv::imgproc::resize;
-use opencv::imgproc::INTER_LINEAR;
 use opencv::prelude::Mat;
 use opencv::prelude::MatTraitConst;
 
 use opencv::prelude::VideoCaptureTrait;
 use opencv::videoio::VideoCapture;
 
+use crossbeam::channel::Sender;
+use opencv::videoio::VideoCaptureTraitConst;
 use opencv::videoio::CAP_ANY;
+use opencv::videoio::CAP_GSTREAMER;
+use opencv::videoio::CAP_PROP_FPS;
+use opencv::videoio::CAP_PROP_POS_MSEC;
+use opencv::videoio::CAP_V4L2;
 use rusted_pipe::channels::typed_write_channel::WriteChannel1;
 use rusted_pipe::graph::processor::ProcessorWriter;
 use rusted_pipe::graph::processor::SourceProcessor;
@@ -19,58 +24,203 @@ use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
+use super::count_frame;
+use super::letterbox::Letterbox;
+
+/// Video shipped with the examples.
+pub const SAMPLE_VIDEO: &str = "data/210112_01_Covid Oxford_4k_061.mp4";
+
+/// Where the frames of a `VideoReader` come from.
+#[derive(Clone, Debug)]
+pub enum VideoSource {
+    /// A file path or any URI OpenCV can open (rtsp://, http://...).
+    File(String),
+    /// A V4L2 device index, 0 for /dev/video0.
+    Device(i32),
+    /// A GStreamer pipeline ending with an appsink.
+    GStreamer(String),
+}
+
+impl VideoSource {
+    /// Guesses the kind of source: integers and /dev/videoN are devices, strings containing
+    /// `!` are GStreamer pipelines and anything else is handed to OpenCV as a file or URI.
+    pub fn parse(source: &str) -> Self {
+        let source = source.trim();
+        let device = source.strip_prefix("/dev/video").unwrap_or(source);
+        if let Ok(index) = device.parse::<i32>() {
+            VideoSource::Device(index)
+        } else if source.contains('!') {
+            VideoSource::GStreamer(source.to_string())
+        } else {
+            VideoSource::File(source.to_string())
+        }
+    }
+
+    fn open(&self) -> Result<VideoCapture, RustedPipeError> {
+        let capture = match self {
+            VideoSource::File(path) => VideoCapture::from_file(path, CAP_ANY),
+            VideoSource::Device(index) => VideoCapture::new(*index, CAP_V4L2),
+            VideoSource::GStreamer(pipeline) => VideoCapture::from_file(pipeline, CAP_GSTREAMER),
+        }
+        .map_err(|e| open_error(self, &e.to_string()))?;
+
+        if !capture
+            .is_opened()
+            .map_err(|e| open_error(self, &e.to_string()))?
+        {
+            return Err(open_error(self, "source not found or not supported"));
+        }
+        Ok(capture)
+    }
+}
+
+fn open_error(source: &VideoSource, reason: &str) -> RustedPipeError {
+    RustedPipeError::ProcessorError(format!("Cannot open video {:?}: {}", source, reason))
+}
+
+/// How the frames of a `VideoReader` are versioned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameClock {
+    /// Time at which the frame was read. Use it for live sources.
+    WallClock,
+    /// Position of the frame in the video, identical across runs of the same file.
+    Media,
+}
+
 pub struct VideoReader {
+    node_id: String,
+    source: VideoSource,
     capture: VideoCapture,
     fps_control: Instant,
     fps_wait: Duration,
     _fps: u64,
     do_loop: bool,
+    end_of_stream: Option<Sender<()>>,
+    output_size: Size,
+    clock: FrameClock,
+    frame_index: u64,
+    loop_offset_ns: u128,
+    last_media_ns: Option<u128>,
 }
 
-fn make_video() -> VideoCapture {
-    VideoCapture::from_file("data/210112_01_Covid Oxford_4k_061.mp4", CAP_ANY).unwrap()
-}
 impl VideoReader {
-    pub fn default(do_loop: bool, fps: usize) -> Self {
-        Self {
-            capture: make_video(),
+    pub fn new(source: VideoSource, do_loop: bool, fps: usize) -> Result<Self, RustedPipeError> {
+        if fps == 0 {
+            return Err(RustedPipeError::ProcessorError(format!(
+                "Invalid fps 0 for {source:?}"
+            )));
+        }
+        Ok(Self {
+            node_id: "video_input".to_string(),
+            capture: source.open()?,
+            source,
             fps_control: Instant::now(),
             fps_wait: Duration::from_millis((1000 / fps) as u64),
             _fps: fps as u64,
             do_loop,
+            end_of_stream: None,
+            output_size: Size::new(1280, 720),
+            clock: FrameClock::WallClock,
+            frame_index: 0,
+            loop_offset_ns: 0,
+            last_media_ns: None,
+        })
+    }
+
+    /// Versions frames with `clock`, `FrameClock::WallClock` by default.
+    pub fn with_clock(mut self, clock: FrameClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn frame_duration_ns(&self) -> u128 {
+        let fps = self.capture.get(CAP_PROP_FPS).unwrap_or(0.0);
+        if fps > 0.0 {
+            (1e9 / fps) as u128
+        } else {
+            1_000_000_000 / self._fps as u128
         }
     }
+
+    /// Version of the last frame read, taken from the container position (CAP_PROP_POS_MSEC).
+    /// When the backend does not report a position the frame index is used instead.
+    /// Versions keep increasing when the video loops.
+    fn media_version(&mut self) -> DataVersion {
+        let frame_ns = self.frame_duration_ns();
+        let position_ms = self.capture.get(CAP_PROP_POS_MSEC).unwrap_or(0.0);
+
+        let position_ns = if position_ms > 0.0 || self.frame_index == 0 {
+            (position_ms.max(0.0) * 1e6) as u128
+        } else {
+            self.frame_index as u128 * frame_ns
+        };
+        let mut timestamp_ns = self.loop_offset_ns + position_ns;
+        if let Some(last) = self.last_media_ns {
+            if timestamp_ns <= last {
+                timestamp_ns = last + frame_ns;
+            }
+        }
+
+        self.frame_index += 1;
+        self.last_media_ns = Some(timestamp_ns);
+        DataVersion { timestamp_ns }
+    }
+
+    /// Size of the frames sent downstream, 1280x720 by default.
+    /// Frames are scaled keeping their aspect ratio and padded with black borders.
+    pub fn with_output_size(mut self, output_size: Size) -> Self {
+        self.output_size = output_size;
+        self
+    }
+
+    /// Sends a message on `sender` once the video has no more frames.
+    pub fn notify_end_of_stream(mut self, sender: Sender<()>) -> Self {
+        self.end_of_stream = Some(sender);
+        self
+    }
+
+    /// Id of the graph node, labelling the frames counted by the reader.
+    pub fn with_node_id(mut self, node_id: &str) -> Self {
+        self.node_id = node_id.to_string();
+        self
+    }
 }
 
 impl SourceProcessor for VideoReader {
     type OUTPUT = WriteChannel1<Mat>;
     fn handle(&mut self, mut output: ProcessorWriter<Self::OUTPUT>) -> Result<(), RustedPipeError> {
         let mut image = Mat::default();
-        let grabbed = self.capture.read(&mut image).unwrap();
+        let grabbed = self
+            .capture
+            .read(&mut image)
+            .map_err(|e| RustedPipeError::ProcessorError(e.to_string()))?;
 
         if !grabbed || image.empty() {
             if self.do_loop {
-                self.capture = make_video();
-                self.capture.read(&mut image).unwrap();
+                let frame_ns = self.frame_duration_ns();
+                self.capture = self.source.open()?;
+                self.frame_index = 0;
+                self.loop_offset_ns = self.last_media_ns.map_or(0, |last| last + frame_ns);
+                self.capture
+                    .read(&mut image)
+                    .map_err(|e| RustedPipeError::ProcessorError(e.to_string()))?;
             } else {
                 println!("Done sending video!");
+                if let Some(end_of_stream) = self.end_of_stream.take() {
+                    end_of_stream.send(()).ok();
+                }
                 return Err(RustedPipeError::EndOfStream());
             }
         }
 
-        let frame_ts = DataVersion::from_now();
+        let frame_ts = match self.clock {
+            FrameClock::WallClock => DataVersion::from_now(),
+            FrameClock::Media => self.media_version(),
+        };
         println!("Frame {}", frame_ts.timestamp_ns);
-        let mut resized = Mat::default();
-        resize(
-            &image,
-            &mut resized,
-            Size::new(1280, 720),
-            0.0,
-            0.0,
-            INTER_LINEAR,
-        )
-        .unwrap();
+        let (resized, _) = Letterbox::apply(&image, self.output_size, Scalar::default());
         output.writer.c1().write(resized, &frame_ts).unwrap();
+        count_frame(&self.node_id);
         let elapsed = self.fps_control.elapsed();
 
         if self.fps_wait > elapsed {